@@ -1,8 +1,14 @@
 use crate::cas_err::CasErr;
 use crate::configs;
+use crate::pg_env;
+use crate::pg_service;
+use crate::postgres::{RegTypeFormat, TextSearchFormat};
+use crate::retry::RetryPolicy;
 use pico_args::Arguments;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
 /*
 TODO:
@@ -17,26 +23,39 @@ TODO:
 pub enum Cmd {
     MainHelp,
     Version,
-    Query(ConnectionParams, String),
+    Query(ConnectionParams, String, QueryOptions),
     QueryHelp,
     ConfigHelp,
     ConfigList,
     ConfigSave(PartialConnectionParams, String),
     ConfigDelete(String),
     ConfigDescribe(String),
+    ConfigImport(Option<String>),
+    ConfigRefreshTypes(ConnectionParams, String),
 }
 
-#[derive(Debug)]
+/// The host may be a comma-separated list of hosts, each optionally followed by :port, which are
+/// tried in order until one accepts the connection and matches the target_session_attrs. The port
+/// is used for any hosts that don’t specify their own.
+///
+/// If read_only is set, the session’s transactions are read-only by default, so the server will
+/// reject any writes.
+#[derive(Clone, Debug)]
 pub struct ConnectionParams {
     pub host: String,
     pub user: String,
     pub password: Option<String>,
     pub database: Option<String>,
     pub port: Option<u16>,
+    // No longer used, since Postgis types are looked up when they are first queried, but kept so
+    // that saved connections and scripts that set it still work.
     pub postgis: bool,
+    pub target_session_attrs: TargetSessionAttrs,
+    pub load_balance_hosts: bool,
+    pub read_only: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct PartialConnectionParams {
     pub host: Option<String>,
     pub user: Option<String>,
@@ -44,6 +63,55 @@ pub struct PartialConnectionParams {
     pub database: Option<String>,
     pub port: Option<u16>,
     pub postgis: bool,
+    pub target_session_attrs: Option<TargetSessionAttrs>,
+    // Options, rather than bools, so that a later layer can turn off what an earlier one set.
+    #[serde(default)]
+    pub load_balance_hosts: Option<bool>,
+    #[serde(default)]
+    pub read_only: Option<bool>,
+}
+
+/// Options for running a query that aren’t part of the connection. Only idempotent or read-only
+/// queries are retried, otherwise the retries only apply to connecting.
+#[derive(Debug)]
+pub struct QueryOptions {
+    pub retry: RetryPolicy,
+    pub idempotent: bool,
+    // The saved connection, if one was used, whose types are cached between queries.
+    pub saved_conn: Option<String>,
+    pub text_search: TextSearchFormat,
+    pub reg_types: RegTypeFormat,
+}
+
+/// The kind of server a connection must be made to, with the same meanings as libpq’s
+/// target_session_attrs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetSessionAttrs {
+    #[default]
+    Any,
+    ReadWrite,
+    ReadOnly,
+    Primary,
+    Standby,
+}
+
+impl FromStr for TargetSessionAttrs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(TargetSessionAttrs::Any),
+            "read-write" => Ok(TargetSessionAttrs::ReadWrite),
+            "read-only" => Ok(TargetSessionAttrs::ReadOnly),
+            "primary" => Ok(TargetSessionAttrs::Primary),
+            "standby" => Ok(TargetSessionAttrs::Standby),
+            _ => Err(format!(
+                "invalid target_session_attrs {}, expected one of any, read-write, read-only, primary or standby",
+                s
+            )),
+        }
+    }
 }
 
 impl fmt::Display for PartialConnectionParams {
@@ -63,6 +131,19 @@ const DATABASE_FLAGS: [&'static str; 2] = ["-d", "--dbname"];
 const POSTGIS_FLAG: &'static str = "--postgis";
 const NAME_FLAGS: [&'static str; 2] = ["-n", "--name"];
 const SAVED_CONN_FLAGS: [&'static str; 2] = ["-c", "--conn"];
+const SERVICE_FLAG: &str = "--service";
+const TARGET_SESSION_ATTRS_FLAG: &str = "--target-session-attrs";
+const LOAD_BALANCE_HOSTS_FLAG: &str = "--load-balance-hosts";
+const NO_LOAD_BALANCE_HOSTS_FLAG: &str = "--no-load-balance-hosts";
+const READ_ONLY_FLAG: &str = "--read-only";
+const NO_READ_ONLY_FLAG: &str = "--no-read-only";
+const RETRIES_FLAG: &str = "--retries";
+const RETRY_BACKOFF_FLAG: &str = "--retry-backoff";
+const IDEMPOTENT_FLAG: &str = "--idempotent";
+const TEXT_SEARCH_FLAG: &str = "--text-search";
+const REG_TYPES_FLAG: &str = "--reg-types";
+
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 100;
 
 const VERSION_TEXT: &str = "casql 0.2.0";
 
@@ -99,11 +180,36 @@ FLAGS:
 
 OPTIONS:
     -c, --conn <CONNECTION>      Use a saved connection
-    -H, --host <HOST>            Database host
+        --service <SERVICE>      Use a service from the connection service file
+    -H, --host <HOST>            Database host, or comma-separated hosts to try in order, each
+                                 optionally with its own :port
     -p, --port <PORT>            Database port
     -d, --dbname <DATABASE>      Database name
     -U, --username <USERNAME>    Database user
     -W, --password <PWD>         Database user’s password
+        --target-session-attrs <ATTRS>
+                                 Only connect to a server that is any (default), read-write,
+                                 read-only, primary or standby
+        --load-balance-hosts     Try the hosts in a random order
+        --no-load-balance-hosts  Try the hosts in order, even if the connection sets
+                                 load_balance_hosts
+        --read-only              Make the session read-only, so the query can safely be retried
+        --no-read-only           Don’t make the session read-only, even if the connection does
+        --retries <N>            Retry connecting up to N times after a network error or failover
+        --retry-backoff <MS>     Wait MS milliseconds before the first retry, doubling each time
+                                 (default 100)
+        --idempotent             Also retry the query itself, which must be safe to run again
+        --text-search <FORMAT>   Write tsvector and tsquery values as text (default), or as
+                                 structured JSON
+        --reg-types <FORMAT>     Write reg* types, like regclass, as oids (default), or as the
+                                 names they refer to
+
+ENVIRONMENT:
+    Parameters that aren’t given as options, in the saved connection or in the service are read
+    from PGHOST, PGPORT, PGUSER, PGPASSWORD, PGDATABASE, PGTARGETSESSIONATTRS and
+    PGLOADBALANCEHOSTS. PGSERVICE sets the service if --service isn’t given. If there is still no
+    password and the server asks for one, it is looked up in the password file, $PGPASSFILE or
+    ~/.pgpass.
 ";
 
 const HELP_TEXT_CONNS: &str = "\
@@ -122,6 +228,10 @@ SUBCOMMANDS:
     describe    Describe a saved connection
     save        Save a connection
     delete      Delete a saved connection
+    import      Save the services from the connection service file as connections
+    refresh-types
+                Look up the types of a saved connection’s database again, replacing those saved
+                for it
     help        Prints this message or the help of the given subcommand(s)
 ";
 
@@ -173,7 +283,17 @@ fn parse_query(args: &mut Arguments) -> Result<Cmd, CasErr> {
     let password: Option<String> = args.opt_value_from_str(PASSWORD_FLAGS)?;
     let database: Option<String> = args.opt_value_from_str(DATABASE_FLAGS)?;
     let postgis: bool = args.contains(POSTGIS_FLAG);
+    let target_session_attrs: Option<TargetSessionAttrs> =
+        args.opt_value_from_str(TARGET_SESSION_ATTRS_FLAG)?;
+    let load_balance_hosts = switch(args, LOAD_BALANCE_HOSTS_FLAG, NO_LOAD_BALANCE_HOSTS_FLAG);
+    let read_only = switch(args, READ_ONLY_FLAG, NO_READ_ONLY_FLAG);
+    let retries: Option<u32> = args.opt_value_from_str(RETRIES_FLAG)?;
+    let retry_backoff: Option<u64> = args.opt_value_from_str(RETRY_BACKOFF_FLAG)?;
+    let idempotent: bool = args.contains(IDEMPOTENT_FLAG);
+    let text_search: Option<TextSearchFormat> = args.opt_value_from_str(TEXT_SEARCH_FLAG)?;
+    let reg_types: Option<RegTypeFormat> = args.opt_value_from_str(REG_TYPES_FLAG)?;
     let conn_name: Option<String> = args.opt_value_from_str(SAVED_CONN_FLAGS)?;
+    let service: Option<String> = args.opt_value_from_str(SERVICE_FLAG)?;
     // then the query.
     let query = args.free_from_str().map_err(|err| {
         if let pico_args::Error::MissingArgument = err {
@@ -189,12 +309,22 @@ fn parse_query(args: &mut Arguments) -> Result<Cmd, CasErr> {
         password,
         database,
         postgis,
+        target_session_attrs,
+        load_balance_hosts,
+        read_only,
     };
-    let params = match conn_name {
-        Some(conn_name) => merge_params(conn_name, supplied_params),
-        None => validate_params(supplied_params),
-    }?;
-    Ok(Cmd::Query(params, query))
+    let params = merge_params(conn_name.clone(), service, supplied_params)?;
+    let options = QueryOptions {
+        retry: RetryPolicy {
+            retries: retries.unwrap_or(0),
+            backoff: Duration::from_millis(retry_backoff.unwrap_or(DEFAULT_RETRY_BACKOFF_MS)),
+        },
+        idempotent,
+        saved_conn: conn_name,
+        text_search: text_search.unwrap_or_default(),
+        reg_types: reg_types.unwrap_or_default(),
+    };
+    Ok(Cmd::Query(params, query, options))
 }
 
 fn parse_conns(args: &mut Arguments) -> Result<Cmd, CasErr> {
@@ -206,6 +336,8 @@ fn parse_conns(args: &mut Arguments) -> Result<Cmd, CasErr> {
         Some("save") => parse_conn_save(args),
         Some("delete") => parse_conn_delete(args),
         Some("describe") => parse_conn_describe(args),
+        Some("import") => parse_conn_import(args),
+        Some("refresh-types") => parse_conn_refresh_types(args),
         Some(_) => Ok(Cmd::MainHelp),
         None => Ok(Cmd::MainHelp),
     }
@@ -219,6 +351,10 @@ fn parse_conn_save(args: &mut Arguments) -> Result<Cmd, CasErr> {
     let password: Option<String> = args.opt_value_from_str(PASSWORD_FLAGS)?;
     let database: Option<String> = args.opt_value_from_str(DATABASE_FLAGS)?;
     let postgis: bool = args.contains(POSTGIS_FLAG);
+    let target_session_attrs: Option<TargetSessionAttrs> =
+        args.opt_value_from_str(TARGET_SESSION_ATTRS_FLAG)?;
+    let load_balance_hosts = switch(args, LOAD_BALANCE_HOSTS_FLAG, NO_LOAD_BALANCE_HOSTS_FLAG);
+    let read_only = switch(args, READ_ONLY_FLAG, NO_READ_ONLY_FLAG);
     Ok(Cmd::ConfigSave(
         PartialConnectionParams {
             host,
@@ -227,6 +363,9 @@ fn parse_conn_save(args: &mut Arguments) -> Result<Cmd, CasErr> {
             password,
             database,
             postgis,
+            target_session_attrs,
+            load_balance_hosts,
+            read_only,
         },
         name,
     ))
@@ -242,19 +381,59 @@ fn parse_conn_describe(args: &mut Arguments) -> Result<Cmd, CasErr> {
     Ok(Cmd::ConfigDescribe(name))
 }
 
+fn parse_conn_import(args: &mut Arguments) -> Result<Cmd, CasErr> {
+    let service: Option<String> = args.opt_value_from_str(SERVICE_FLAG)?;
+    Ok(Cmd::ConfigImport(service))
+}
+
+fn parse_conn_refresh_types(args: &mut Arguments) -> Result<Cmd, CasErr> {
+    let name: String = args.value_from_str(NAME_FLAGS)?;
+    let params = merge_params(
+        Some(name.clone()),
+        None,
+        PartialConnectionParams::default(),
+    )?;
+    Ok(Cmd::ConfigRefreshTypes(params, name))
+}
+
+/// Each parameter is taken from the first of these that sets it:
+/// 1. the command line options
+/// 2. the saved connection, if one is specified
+/// 3. the service, if one is specified with --service or PGSERVICE
+/// 4. the PG* environment variables
+///
+/// A password that still isn’t set is looked up in the password file when connecting.
 fn merge_params(
-    conn_name: String,
+    conn_name: Option<String>,
+    service: Option<String>,
     supplied_params: PartialConnectionParams,
 ) -> Result<ConnectionParams, CasErr> {
-    let loaded_params = configs::load(conn_name)?;
-    validate_params(PartialConnectionParams {
-        host: supplied_params.host.or(loaded_params.host),
-        port: supplied_params.port.or(loaded_params.port),
-        user: supplied_params.user.or(loaded_params.user),
-        password: supplied_params.password.or(loaded_params.password),
-        database: supplied_params.database.or(loaded_params.database),
-        postgis: supplied_params.postgis || loaded_params.postgis,
-    })
+    let mut params = supplied_params;
+    if let Some(conn_name) = conn_name {
+        params = layer_params(params, configs::load(conn_name)?);
+    }
+    if let Some(service) = service.or_else(pg_env::service) {
+        params = layer_params(params, pg_service::load(&service)?);
+    }
+    params = layer_params(params, pg_env::load()?);
+    validate_params(params)
+}
+
+fn layer_params(
+    over: PartialConnectionParams,
+    under: PartialConnectionParams,
+) -> PartialConnectionParams {
+    PartialConnectionParams {
+        host: over.host.or(under.host),
+        port: over.port.or(under.port),
+        user: over.user.or(under.user),
+        password: over.password.or(under.password),
+        database: over.database.or(under.database),
+        postgis: over.postgis || under.postgis,
+        target_session_attrs: over.target_session_attrs.or(under.target_session_attrs),
+        load_balance_hosts: over.load_balance_hosts.or(under.load_balance_hosts),
+        read_only: over.read_only.or(under.read_only),
+    }
 }
 
 fn validate_params(params: PartialConnectionParams) -> Result<ConnectionParams, CasErr> {
@@ -279,6 +458,9 @@ fn validate_params(params: PartialConnectionParams) -> Result<ConnectionParams,
             password,
             postgis,
             port,
+            target_session_attrs,
+            load_balance_hosts,
+            read_only,
         } => Ok(ConnectionParams {
             host,
             user,
@@ -286,6 +468,44 @@ fn validate_params(params: PartialConnectionParams) -> Result<ConnectionParams,
             database,
             port,
             postgis,
+            target_session_attrs: target_session_attrs.unwrap_or_default(),
+            load_balance_hosts: load_balance_hosts.unwrap_or(false),
+            read_only: read_only.unwrap_or(false),
         }),
     }
 }
+
+/// A flag that can be turned on or off, e.g. --read-only and --no-read-only, or None if neither is
+/// given, so that it is left to the saved connection, service or environment.
+fn switch(args: &mut Arguments, on: &'static str, off: &'static str) -> Option<bool> {
+    if args.contains(on) {
+        Some(true)
+    } else if args.contains(off) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::{layer_params, PartialConnectionParams};
+
+    #[test]
+    fn test_layer_params() {
+        let cli = PartialConnectionParams {
+            read_only: Some(false),
+            ..PartialConnectionParams::default()
+        };
+        let service = PartialConnectionParams {
+            host: Some("db1".to_owned()),
+            load_balance_hosts: Some(true),
+            read_only: Some(true),
+            ..PartialConnectionParams::default()
+        };
+        let params = layer_params(cli, service);
+        assert_eq!(params.host.as_deref(), Some("db1"));
+        assert_eq!(params.load_balance_hosts, Some(true));
+        assert_eq!(params.read_only, Some(false));
+    }
+}
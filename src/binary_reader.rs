@@ -18,6 +18,12 @@ impl<'a> BinaryReader<'a> {
         }
     }
 
+    /// Changes the byte order of the values read after this, for formats like WKB where each part
+    /// can have its own.
+    pub fn set_order(&mut self, order: ByteOrder) {
+        self.order = order;
+    }
+
     pub fn skip(&mut self, n: usize) {
         self.pos = self.pos + n;
     }
@@ -103,7 +109,7 @@ impl<'a> BinaryReader<'a> {
         s
     }
 
-    pub fn byte_slice(&mut self, len: usize) -> &[u8] {
+    pub fn byte_slice(&mut self, len: usize) -> &'a [u8] {
         let slice = &self.bytes[self.pos..(self.pos + len)];
         self.skip(len);
         slice
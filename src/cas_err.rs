@@ -7,27 +7,69 @@ use std::io::ErrorKind;
 pub enum CasErr {
     ArgErr(String),
     ConfigsErr(String),
+    DecodeErr(String),
     IoBrokenPipe,
     IoConnRefused,
+    /// The connection to the server was closed or reset while it was in use.
+    IoConnLost(String),
     IoErr(String),
     PostgresErr(String),
+    /// An ErrorResponse from the server, with its SQLSTATE code.
+    ServerErr { code: String, message: String },
     Utf8Err(String),
 }
 
+// SQLSTATEs for errors that may succeed if the same thing is tried again, because the server was
+// restarting or failing over, or the transaction lost out to a concurrent one.
+const TRANSIENT_SQLSTATES: [&str; 5] = [
+    "40001", // serialization_failure
+    "40P01", // deadlock_detected
+    "57P01", // admin_shutdown
+    "57P02", // crash_shutdown
+    "57P03", // cannot_connect_now
+];
+
+impl CasErr {
+    /// Whether the error may be caused by a brief network interruption or failover, so that it is
+    /// worth retrying. Other IO errors, such as those writing the output, are not.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CasErr::IoConnRefused => true,
+            CasErr::IoConnLost(_) => true,
+            CasErr::ServerErr { code, .. } => {
+                // Class 08 — Connection Exception
+                code.starts_with("08") || TRANSIENT_SQLSTATES.contains(&code.as_str())
+            }
+            _ => false,
+        }
+    }
+}
+
 impl Display for CasErr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             CasErr::ArgErr(msg) => write!(f, "{}", msg),
             CasErr::ConfigsErr(msg) => write!(f, "Configs Error: {}", msg),
+            CasErr::DecodeErr(msg) => write!(f, "Decode Error: {}", msg),
             CasErr::IoBrokenPipe => write!(f, ""), // ignore SIGPIPEs
             CasErr::IoConnRefused => write!(f, "IO Error: could not connect to database"),
+            CasErr::IoConnLost(msg) => write!(f, "IO Error: {}", msg),
             CasErr::IoErr(msg) => write!(f, "IO Error: {}", msg),
             CasErr::PostgresErr(msg) => write!(f, "Postgres Error: {}", msg),
+            CasErr::ServerErr { message, .. } => write!(f, "Postgres Error: {}", message),
             CasErr::Utf8Err(msg) => write!(f, "UTF-8 Error: {}", msg),
         }
     }
 }
 
+impl std::error::Error for CasErr {}
+
+impl serde::de::Error for CasErr {
+    fn custom<T: Display>(msg: T) -> Self {
+        CasErr::DecodeErr(msg.to_string())
+    }
+}
+
 impl From<io::Error> for CasErr {
     fn from(err: io::Error) -> Self {
         // eprintln!("{:?}", err.kind());
@@ -1,5 +1,7 @@
 use crate::args::PartialConnectionParams;
 use crate::cas_err::CasErr;
+use crate::pg_service;
+use crate::type_cache;
 use std::collections::HashMap;
 use std::fs;
 use std::io;
@@ -111,6 +113,7 @@ pub fn delete(name: String) -> Result<(), CasErr> {
         match connection_map.remove(&name) {
             Some(_) => {
                 write_conns(connection_map)?;
+                type_cache::remove(&name)?;
                 writeln(&format!("Connection deleted: {}", name))
             }
             None => Err(CasErr::ArgErr(format!("Connection {} not found.", name))),
@@ -120,7 +123,37 @@ pub fn delete(name: String) -> Result<(), CasErr> {
     }
 }
 
-fn config_dir_path() -> Result<path::PathBuf, CasErr> {
+/// Copies services from the connection service files into the saved connections, replacing any
+/// saved connections with the same names. If a service name is given, only that one is imported.
+pub fn import(service: Option<String>) -> Result<(), CasErr> {
+    let services = match service {
+        Some(name) => {
+            let params = pg_service::load(&name)?;
+            vec![(name, params)]
+        }
+        None => pg_service::load_all()?.into_iter().collect(),
+    };
+    if services.is_empty() {
+        return writeln("No services found.");
+    }
+    let mut connection_map = match read_conns()? {
+        Some(conns) => conns,
+        None => {
+            initialise_config()?;
+            HashMap::new()
+        }
+    };
+    let mut names: Vec<String> = services.iter().map(|(name, _)| name.clone()).collect();
+    names.sort();
+    connection_map.extend(services);
+    write_conns(connection_map)?;
+    for name in names {
+        writeln(&format!("Connection {} imported.", name))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn config_dir_path() -> Result<path::PathBuf, CasErr> {
     let mut config_dir = dirs::config_dir().ok_or(CasErr::ConfigsErr(
         "Cannot locate config directory to save connections.".to_owned(),
     ))?;
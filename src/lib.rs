@@ -2,4 +2,9 @@ pub mod args;
 mod binary_reader;
 pub mod cas_err;
 pub mod configs;
+mod pg_env;
+mod pg_service;
+mod pgpass;
 pub mod postgres;
+pub mod retry;
+pub mod type_cache;
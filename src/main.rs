@@ -1,8 +1,11 @@
 use casql::args;
-use casql::args::Cmd;
+use casql::args::{Cmd, ConnectionParams, QueryOptions};
 use casql::cas_err::CasErr;
 use casql::configs;
 use casql::postgres::connection::Conn;
+use casql::postgres::{RegTypeFormat, Registry, TextSearchFormat};
+use casql::retry::HeldOutput;
+use casql::type_cache;
 use std::io::BufWriter;
 
 fn main() {
@@ -20,19 +23,63 @@ fn run() -> Result<(), CasErr> {
     match args {
         Cmd::MainHelp => args::print_main_help(),
         Cmd::Version => args::print_version(),
-        Cmd::Query(conn_params, query) => {
-            let stdout = std::io::stdout();
-            let handle = stdout.lock();
-            let mut out = BufWriter::new(handle);
-            let mut conn = Conn::connect(conn_params)?;
-            conn.query(query, vec![], &mut out)
-        }
+        Cmd::Query(conn_params, query, options) => run_query(conn_params, query, options),
         Cmd::QueryHelp => args::print_query_help(),
         Cmd::ConfigList => configs::list(),
         Cmd::ConfigSave(conn_params, name) => configs::save(name, conn_params),
         Cmd::ConfigDelete(name) => configs::delete(name),
         Cmd::ConfigDescribe(name) => configs::describe(name),
+        Cmd::ConfigImport(service) => configs::import(service),
+        Cmd::ConfigRefreshTypes(conn_params, name) => type_cache::refresh(conn_params, name),
         Cmd::ConfigHelp => args::print_conns_help(),
     }?;
     Ok(())
 }
+
+/// Connection errors are retried according to the retry policy. Errors from the query itself are
+/// only retried if it is safe to run it again, and none of its output has been written yet.
+///
+/// With a saved connection, the types looked up in pg_type by earlier queries are reused, and any
+/// new ones are saved for later queries.
+fn run_query(params: ConnectionParams, query: String, options: QueryOptions) -> Result<(), CasErr> {
+    let stdout = std::io::stdout();
+    let handle = stdout.lock();
+    let retry_query = options.idempotent || params.read_only;
+    let mut out = HeldOutput::new(BufWriter::new(handle), retry_query);
+    let connect = || {
+        let mut conn = Conn::connect(params.clone())?;
+        if options.text_search != TextSearchFormat::Text || options.reg_types != RegTypeFormat::Oid
+        {
+            let mut registry = Registry::new();
+            registry.set_text_search_format(options.text_search);
+            registry.set_reg_type_format(options.reg_types);
+            conn.set_registry(registry)?;
+        }
+        if let Some(name) = &options.saved_conn {
+            type_cache::load(name, &mut conn);
+        }
+        Ok(conn)
+    };
+    let mut conn = options.retry.run(connect)?;
+    let mut attempt = 0;
+    loop {
+        match conn.query(query.clone(), vec![], &mut out) {
+            Err(err)
+                if retry_query
+                    && err.is_transient()
+                    && attempt < options.retry.retries
+                    && out.discard() =>
+            {
+                options.retry.wait(attempt, &err);
+                attempt += 1;
+                conn = options.retry.run(connect)?;
+            }
+            result => {
+                if let (Ok(_), Some(name)) = (&result, &options.saved_conn) {
+                    type_cache::save_if_looked_up(name, &conn);
+                }
+                return result;
+            }
+        }
+    }
+}
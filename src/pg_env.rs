@@ -0,0 +1,87 @@
+use crate::args::{PartialConnectionParams, TargetSessionAttrs};
+use crate::cas_err::CasErr;
+use crate::postgres::connection::join_host_ports;
+use std::env;
+
+/// Loads connection parameters from the environment variables used by libpq, so that casql works
+/// in environments where psql is already configured. Empty variables are treated as unset.
+///
+/// casql doesn’t support SSL connections, so if PGSSLMODE requires one, it is an error rather than
+/// silently connecting without it.
+///
+/// As in libpq, PGPORT may be a comma-separated list, with a port for each of the hosts in PGHOST.
+pub fn load() -> Result<PartialConnectionParams, CasErr> {
+    check_ssl_mode()?;
+    let mut host = var("PGHOST");
+    let port = match var("PGPORT") {
+        Some(ports) if ports.contains(',') => {
+            let hosts = host.as_deref().ok_or_else(|| {
+                CasErr::ArgErr("PGPORT is a list of ports, but PGHOST isn’t set".to_owned())
+            })?;
+            let hosts = join_host_ports(hosts, &ports)
+                .map_err(|e| CasErr::ArgErr(format!("{} in PGHOST and PGPORT", e)))?;
+            host = Some(hosts);
+            None
+        }
+        Some(port) => Some(
+            port.parse::<u16>()
+                .map_err(|_| CasErr::ArgErr(format!("Invalid port in PGPORT: {}", port)))?,
+        ),
+        None => None,
+    };
+    let target_session_attrs = match var("PGTARGETSESSIONATTRS") {
+        Some(attrs) => Some(
+            attrs
+                .parse::<TargetSessionAttrs>()
+                .map_err(|e| CasErr::ArgErr(format!("Invalid PGTARGETSESSIONATTRS: {}", e)))?,
+        ),
+        None => None,
+    };
+    let load_balance_hosts = match var("PGLOADBALANCEHOSTS").as_deref() {
+        None => None,
+        Some("disable") => Some(false),
+        Some("random") => Some(true),
+        Some(other) => {
+            return Err(CasErr::ArgErr(format!(
+                "Invalid load_balance_hosts in PGLOADBALANCEHOSTS: {}",
+                other
+            )))
+        }
+    };
+    Ok(PartialConnectionParams {
+        host,
+        port,
+        user: var("PGUSER"),
+        password: var("PGPASSWORD"),
+        database: var("PGDATABASE"),
+        postgis: false,
+        target_session_attrs,
+        load_balance_hosts,
+        read_only: None,
+    })
+}
+
+/// The name of the service to use if one isn’t given on the command line.
+pub fn service() -> Option<String> {
+    var("PGSERVICE")
+}
+
+fn check_ssl_mode() -> Result<(), CasErr> {
+    match var("PGSSLMODE").as_deref() {
+        None | Some("disable") | Some("allow") | Some("prefer") => Ok(()),
+        Some(mode @ "require") | Some(mode @ "verify-ca") | Some(mode @ "verify-full") => {
+            Err(CasErr::ArgErr(format!(
+                "PGSSLMODE is {}, but casql doesn’t support SSL connections",
+                mode
+            )))
+        }
+        Some(other) => Err(CasErr::ArgErr(format!(
+            "Invalid sslmode in PGSSLMODE: {}",
+            other
+        ))),
+    }
+}
+
+fn var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
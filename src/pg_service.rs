@@ -0,0 +1,217 @@
+use crate::args::PartialConnectionParams;
+use crate::cas_err::CasErr;
+use crate::postgres::connection::join_host_ports;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path;
+
+const SERVICE_FILENAME: &str = ".pg_service.conf";
+const SYS_SERVICE_FILENAME: &str = "pg_service.conf";
+const DEFAULT_SYSCONFDIR: &str = "/etc";
+
+/// Loads a service definition, looking first in the user’s service file ($PGSERVICEFILE or
+/// ~/.pg_service.conf) and then in the system one ($PGSYSCONFDIR/pg_service.conf). Like libpq, the
+/// first file that defines the service is used, and the definitions aren’t merged.
+pub fn load(name: &str) -> Result<PartialConnectionParams, CasErr> {
+    for path in service_file_paths() {
+        if let Some(mut services) = read_services(&path)? {
+            if let Some(service) = services.remove(name) {
+                return Ok(service);
+            }
+        }
+    }
+    Err(CasErr::ArgErr(format!("Service {} not found.", name)))
+}
+
+/// Loads every service definition from the user and system service files. Where both define the
+/// same service, the user’s definition wins.
+pub fn load_all() -> Result<HashMap<String, PartialConnectionParams>, CasErr> {
+    let mut all_services = HashMap::new();
+    for path in service_file_paths().iter().rev() {
+        if let Some(services) = read_services(path)? {
+            all_services.extend(services);
+        }
+    }
+    Ok(all_services)
+}
+
+fn service_file_paths() -> Vec<path::PathBuf> {
+    let mut paths = Vec::with_capacity(2);
+    match env::var_os("PGSERVICEFILE") {
+        Some(path) => paths.push(path::PathBuf::from(path)),
+        None => {
+            if let Some(mut path) = dirs::home_dir() {
+                path.push(SERVICE_FILENAME);
+                paths.push(path);
+            }
+        }
+    }
+    let mut sys_path = env::var_os("PGSYSCONFDIR")
+        .map(path::PathBuf::from)
+        .unwrap_or_else(|| path::PathBuf::from(DEFAULT_SYSCONFDIR));
+    sys_path.push(SYS_SERVICE_FILENAME);
+    paths.push(sys_path);
+    paths
+}
+
+fn read_services(
+    path: &path::Path,
+) -> Result<Option<HashMap<String, PartialConnectionParams>>, CasErr> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_services(&text)
+            .map(Some)
+            .map_err(|e| CasErr::ConfigsErr(format!("{} in {}", e, path.display()))),
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(CasErr::from(err)),
+    }
+}
+
+/// The service file is an INI file, with a [section] for each service, containing key=value lines
+/// with the same keys as a libpq connection string. Keys for parameters that casql doesn’t use,
+/// such as application_name, are ignored. casql doesn’t support SSL, so an sslmode that requires
+/// it is an error, as it is in PGSSLMODE.
+///
+/// As in libpq, the port may be a comma-separated list, with a port for each of the hosts.
+fn parse_services(text: &str) -> Result<HashMap<String, PartialConnectionParams>, String> {
+    let mut services = HashMap::new();
+    let mut current: Option<(String, PartialConnectionParams)> = None;
+    // A list of ports, and the line it is on, which is paired with the hosts at the end of the
+    // service, since the host may come after it.
+    let mut ports: Option<(String, usize)> = None;
+    for (idx, raw_line) in text.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            add_service(&mut services, current.take(), ports.take())?;
+            let name = line[1..line.len() - 1].trim().to_owned();
+            current = Some((name, PartialConnectionParams::default()));
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(format!("Syntax error on line {}", idx + 1)),
+        };
+        let params = match current.as_mut() {
+            Some((_, params)) => params,
+            None => return Err(format!("Parameter outside of a service on line {}", idx + 1)),
+        };
+        match key {
+            "host" => params.host = Some(value.to_owned()),
+            "port" if value.contains(',') => ports = Some((value.to_owned(), idx + 1)),
+            "port" => {
+                let port = value
+                    .parse::<u16>()
+                    .map_err(|_| format!("Invalid port on line {}", idx + 1))?;
+                params.port = Some(port);
+            }
+            "user" => params.user = Some(value.to_owned()),
+            "password" => params.password = Some(value.to_owned()),
+            "dbname" => params.database = Some(value.to_owned()),
+            "target_session_attrs" => {
+                let attrs = value
+                    .parse()
+                    .map_err(|e| format!("{} on line {}", e, idx + 1))?;
+                params.target_session_attrs = Some(attrs);
+            }
+            "sslmode" => match value {
+                "disable" | "allow" | "prefer" => {}
+                "require" | "verify-ca" | "verify-full" => {
+                    return Err(format!(
+                        "sslmode is {} on line {}, but casql doesn’t support SSL connections",
+                        value,
+                        idx + 1
+                    ))
+                }
+                _ => return Err(format!("Invalid sslmode on line {}", idx + 1)),
+            },
+            "load_balance_hosts" => {
+                params.load_balance_hosts = match value {
+                    "disable" => Some(false),
+                    "random" => Some(true),
+                    _ => return Err(format!("Invalid load_balance_hosts on line {}", idx + 1)),
+                }
+            }
+            _ => {}
+        }
+    }
+    add_service(&mut services, current, ports)?;
+    Ok(services)
+}
+
+/// Adds the service, with its list of ports, if it has one, paired with its hosts. casql keeps
+/// each host’s port with it, so they become a list like db1:5433,db2:5434.
+fn add_service(
+    services: &mut HashMap<String, PartialConnectionParams>,
+    service: Option<(String, PartialConnectionParams)>,
+    ports: Option<(String, usize)>,
+) -> Result<(), String> {
+    let (name, mut params) = match service {
+        Some(service) => service,
+        None => return Ok(()),
+    };
+    if let Some((ports, line)) = ports {
+        let hosts = params
+            .host
+            .as_deref()
+            .ok_or_else(|| format!("List of ports without a list of hosts on line {}", line))?;
+        let hosts =
+            join_host_ports(hosts, &ports).map_err(|e| format!("{} on line {}", e, line))?;
+        params.host = Some(hosts);
+    }
+    services.insert(name, params);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pg_service::parse_services;
+
+    #[test]
+    fn test_parse_services() {
+        let text = "\
+# Our databases
+[reporting]
+host=reports.example.com
+port=5433
+dbname = reporting
+user=analyst
+sslmode=disable
+
+[local]
+host=localhost
+";
+        let services = parse_services(text).unwrap();
+        let reporting = &services["reporting"];
+        assert_eq!(reporting.host.as_deref(), Some("reports.example.com"));
+        assert_eq!(reporting.port, Some(5433));
+        assert_eq!(reporting.database.as_deref(), Some("reporting"));
+        assert_eq!(reporting.user.as_deref(), Some("analyst"));
+        assert_eq!(reporting.password, None);
+        assert_eq!(services["local"].host.as_deref(), Some("localhost"));
+    }
+
+    #[test]
+    fn test_parse_services_port_list() {
+        let text = "[cluster]\nport=5433,5434\nhost=db1,db2\n";
+        let services = parse_services(text).unwrap();
+        let cluster = &services["cluster"];
+        assert_eq!(cluster.host.as_deref(), Some("db1:5433,db2:5434"));
+        assert_eq!(cluster.port, None);
+    }
+
+    #[test]
+    fn test_parse_services_errors() {
+        assert!(parse_services("host=localhost\n").is_err());
+        assert!(parse_services("[local]\nhost\n").is_err());
+        assert!(parse_services("[local]\nport=high\n").is_err());
+        assert!(parse_services("[local]\nsslmode=require\n").is_err());
+        assert!(parse_services("[local]\nsslmode=verify-full\n").is_err());
+        assert!(parse_services("[local]\nsslmode=sometimes\n").is_err());
+        assert!(parse_services("[local]\nload_balance_hosts=shuffle\n").is_err());
+        assert!(parse_services("[local]\nport=5433,5434\n").is_err());
+        assert!(parse_services("[local]\nhost=db1,db2,db3\nport=5433,5434\n").is_err());
+    }
+}
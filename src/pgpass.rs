@@ -0,0 +1,139 @@
+use crate::cas_err::CasErr;
+use std::fs;
+use std::path;
+
+const PGPASS_FILENAME: &str = ".pgpass";
+
+/// Looks up a password in the password file, following the same rules as libpq. The file is read
+/// from $PGPASSFILE if it is set, otherwise from ~/.pgpass. Each line has the format
+/// hostname:port:database:username:password, where any of the first four fields may be a *
+/// wildcard, and colons and backslashes within a field are escaped with a backslash. The first
+/// matching line wins.
+///
+/// A missing file isn’t an error, it just means there’s no password. Like libpq, the file is
+/// ignored if it is readable by anyone but its owner.
+pub fn find_password(
+    host: &str,
+    port: u16,
+    database: &str,
+    user: &str,
+) -> Result<Option<String>, CasErr> {
+    let path = match pgpass_path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(CasErr::from(err)),
+    };
+    if !permissions_ok(&path)? {
+        eprintln!(
+            "WARNING: password file {} has group or world access; permissions should be u=rw (0600) or less",
+            path.display()
+        );
+        return Ok(None);
+    }
+    Ok(match_password(&text, host, &port.to_string(), database, user))
+}
+
+fn pgpass_path() -> Option<path::PathBuf> {
+    match std::env::var_os("PGPASSFILE") {
+        Some(path) => Some(path::PathBuf::from(path)),
+        None => dirs::home_dir().map(|mut path| {
+            path.push(PGPASS_FILENAME);
+            path
+        }),
+    }
+}
+
+#[cfg(unix)]
+fn permissions_ok(path: &path::Path) -> Result<bool, CasErr> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode();
+    Ok(mode & 0o077 == 0)
+}
+
+#[cfg(not(unix))]
+fn permissions_ok(_path: &path::Path) -> Result<bool, CasErr> {
+    Ok(true)
+}
+
+fn match_password(text: &str, host: &str, port: &str, database: &str, user: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(split_fields)
+        .filter(|fields| fields.len() == 5)
+        .find(|fields| {
+            field_matches(&fields[0], host)
+                && field_matches(&fields[1], port)
+                && field_matches(&fields[2], database)
+                && field_matches(&fields[3], user)
+        })
+        .map(|mut fields| fields.remove(4))
+}
+
+fn field_matches(field: &str, value: &str) -> bool {
+    field == "*" || field == value
+}
+
+/// Splits a line on unescaped colons, removing the escaping backslashes. The password is the last
+/// field, so any colons after the fourth one are kept as part of it.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::with_capacity(5);
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    field.push(escaped);
+                }
+            }
+            ':' if fields.len() < 4 => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgpass::match_password;
+
+    #[test]
+    fn test_match_password() {
+        let text = "\
+# comment
+db.example.com:5432:app:alice:first
+*:*:app:bob:second
+localhost:*:*:*:third
+";
+        assert_eq!(
+            match_password(text, "db.example.com", "5432", "app", "alice"),
+            Some("first".to_owned())
+        );
+        assert_eq!(
+            match_password(text, "db.example.com", "5433", "app", "bob"),
+            Some("second".to_owned())
+        );
+        assert_eq!(
+            match_password(text, "localhost", "5432", "other", "carol"),
+            Some("third".to_owned())
+        );
+        assert_eq!(
+            match_password(text, "db.example.com", "5433", "app", "alice"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_match_password_escapes() {
+        let text = r"my\:host:5432:db:user:pa\:ss\\word:with:colons";
+        assert_eq!(
+            match_password(text, "my:host", "5432", "db", "user"),
+            Some(r"pa:ss\word:with:colons".to_owned())
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::args::ConnectionParams;
+use crate::cas_err::CasErr;
+use crate::postgres::backend_msgs;
+use crate::postgres::backend_msgs::{BackendMsg, PgType};
+use crate::postgres::connection::{parse_hosts, shuffle, DEFAULT_PORT};
+use crate::postgres::output::registry::Registry;
+use crate::postgres::output::sink::Sink;
+use crate::postgres::output::write;
+use crate::postgres::output::write::{JsonSink, OutputField};
+use crate::postgres::params::Param;
+use crate::postgres::protocol;
+use crate::postgres::protocol::{
+    BufferRows, CheckSessionAttrs, Exchange, Query, RowMsg, Session, Startup, Step, TypeLookup,
+};
+use std::collections::VecDeque;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// An async version of connection::Conn, for use within a tokio runtime. It sends the same
+/// messages and writes the rows with the same serialisers, but never blocks the runtime’s threads.
+#[derive(Debug)]
+pub struct Conn {
+    stream: BufReader<TcpStream>,
+    session: Session,
+    // Set while the response to a query hasn’t been read up to its ReadyForQuery, which happens if
+    // a RowStream is dropped before the end.
+    in_query: bool,
+    // The rest of a response that was read ahead, to look up the field types of its records.
+    buffered: VecDeque<Vec<u8>>,
+}
+
+impl Conn {
+    /// Connects to the first host that accepts the connection and matches the
+    /// target_session_attrs. If none of them do, the error from the last host is returned.
+    pub async fn connect(params: ConnectionParams) -> Result<Self, CasErr> {
+        let mut hosts = parse_hosts(&params.host, params.port.unwrap_or(DEFAULT_PORT))?;
+        if params.load_balance_hosts {
+            shuffle(&mut hosts);
+        }
+        let mut last_err = None;
+        for (host, port) in hosts {
+            match Conn::connect_host(&params, &host, port).await {
+                Ok(conn) => return Ok(conn),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.expect("There is always at least one host."))
+    }
+
+    /// The parameters the server reported at startup, such as server_version or TimeZone.
+    pub fn server_param(&self, name: &str) -> Option<&str> {
+        self.session
+            .server_params
+            .get(name)
+            .map(|value| value.as_str())
+    }
+
+    async fn connect_host(
+        params: &ConnectionParams,
+        host: &str,
+        port: u16,
+    ) -> Result<Self, CasErr> {
+        let mut conn = Conn {
+            stream: BufReader::new(TcpStream::connect((host, port)).await?),
+            session: Session::default(),
+            in_query: false,
+            buffered: VecDeque::new(),
+        };
+        conn.exchange(Startup::new(params, host, port)).await?;
+        if let Some(reason) = conn
+            .exchange(CheckSessionAttrs::new(params.target_session_attrs))
+            .await?
+        {
+            return Err(CasErr::PostgresErr(format!(
+                "Rejected server {}:{}: {}",
+                host, port, reason
+            )));
+        }
+        Ok(conn)
+    }
+
+    /// The types that have been looked up in pg_type, as connection::Conn::dynamic_types.
+    pub fn dynamic_types(&self) -> Vec<PgType> {
+        self.session.registry.dynamic_types()
+    }
+
+    /// Loads types looked up by an earlier connection to the same server. If a query returns a
+    /// type that isn’t among them, they are assumed to be out of date, and are looked up again.
+    pub fn load_types(&mut self, types: Vec<PgType>) {
+        self.session.registry.load_types(types);
+    }
+
+    /// Replaces the serialisers used for the query results, and looks up the oids of the types
+    /// registered by name.
+    pub async fn set_registry(&mut self, registry: Registry) -> Result<(), CasErr> {
+        if self.in_query {
+            self.skip_to_ready().await?;
+        }
+        let previous = std::mem::replace(&mut self.session.registry, registry);
+        self.session.registry.inherit_types(previous);
+        let lookup = TypeLookup::by_name(&self.session.registry)?;
+        self.exchange(lookup).await
+    }
+
+    /// Runs the query, and writes the rows to out as a JSON array, one row at a time.
+    pub async fn query<Out>(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+        out: &mut Out,
+    ) -> Result<(), CasErr>
+    where
+        Out: AsyncWrite + Unpin,
+    {
+        let mut rows = self.stream(query, params).await?;
+        // The rows are written to a buffer, which is copied to out after each row.
+        let mut sink = JsonSink::new(Vec::new());
+        sink.begin_result(&rows.columns())?;
+        while let Some(row) = rows.next_to(&mut sink).await {
+            row?;
+            out.write_all(sink.get_mut()).await?;
+            sink.get_mut().clear();
+        }
+        sink.end_result()?;
+        out.write_all(sink.get_mut()).await?;
+        out.flush().await?;
+        Ok(())
+    }
+
+    /// Runs the query, and passes the rows to the sink, one row at a time. The sink’s methods are
+    /// not async, so it should write to memory rather than block.
+    pub async fn query_to<S>(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+        sink: &mut S,
+    ) -> Result<(), CasErr>
+    where
+        S: Sink + ?Sized,
+    {
+        let mut rows = self.stream(query, params).await?;
+        sink.begin_result(&rows.columns())?;
+        while let Some(row) = rows.next_to(sink).await {
+            row?;
+        }
+        sink.end_result()
+    }
+
+    /// Runs the query, and returns a stream of its rows, each serialised as a JSON object. If the
+    /// stream is dropped early, the rest of the rows are skipped before the next query. If the
+    /// query returns records, or reg* values to be named, its whole response is read into memory
+    /// first, as connection::Conn does.
+    pub async fn stream(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+    ) -> Result<RowStream<'_>, CasErr> {
+        if self.in_query {
+            self.skip_to_ready().await?;
+        }
+        let fields = self.exchange(Query::new(&query, &params)).await?;
+        if self.session.registry.needs_row_pass(&fields) {
+            self.buffered = self.exchange(BufferRows::new(&fields)).await?;
+        }
+        self.in_query = true;
+        let fields = write::output_fields(&fields, &self.session.registry);
+        Ok(RowStream { conn: self, fields })
+    }
+
+    /// Writes and reads the messages of the exchange, until it is done.
+    async fn exchange<E: Exchange>(&mut self, mut exchange: E) -> Result<E::Output, CasErr> {
+        let mut step = exchange.start(&mut self.session)?;
+        loop {
+            match step {
+                Step::Send(msgs) => self
+                    .stream
+                    .write_all(&msgs)
+                    .await
+                    .map_err(protocol::socket_err)?,
+                Step::Read => {}
+                Step::Done(output) => return Ok(output),
+            }
+            let msg = self.read_msg().await?;
+            step = exchange.handle(&mut self.session, msg)?;
+        }
+    }
+
+    /// Reads one message. Messages that were read ahead come first.
+    async fn read_msg(&mut self) -> Result<Vec<u8>, CasErr> {
+        if let Some(msg) = self.buffered.pop_front() {
+            return Ok(msg);
+        }
+        let mut msg = vec![0; 5];
+        self.stream
+            .read_exact(&mut msg)
+            .await
+            .map_err(protocol::socket_err)?;
+        msg.resize(protocol::msg_len(&msg), 0);
+        self.stream
+            .read_exact(&mut msg[5..])
+            .await
+            .map_err(protocol::socket_err)?;
+        Ok(msg)
+    }
+
+    async fn skip_to_ready(&mut self) -> Result<(), CasErr> {
+        loop {
+            let msg = self.read_msg().await?;
+            if let BackendMsg::ReadyForQuery = backend_msgs::type_of(&msg) {
+                self.in_query = false;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The rows of a query, read from the connection as they are needed.
+pub struct RowStream<'conn> {
+    conn: &'conn mut Conn,
+    fields: Vec<OutputField>,
+}
+
+impl<'conn> RowStream<'conn> {
+    /// The names of the columns.
+    pub fn columns(&self) -> Vec<String> {
+        write::column_names(&self.fields)
+    }
+
+    /// Returns the next row as a JSON object, or None once all the rows have been read.
+    pub async fn next(&mut self) -> Option<Result<Vec<u8>, CasErr>> {
+        let mut sink = JsonSink::new(Vec::new());
+        let result = self.next_to(&mut sink).await?;
+        Some(result.map(|_| sink.into_inner()))
+    }
+
+    /// Passes the next row to the sink, from begin_row to end_row, or returns None once all the
+    /// rows have been read.
+    pub async fn next_to<S>(&mut self, sink: &mut S) -> Option<Result<(), CasErr>>
+    where
+        S: Sink + ?Sized,
+    {
+        if !self.conn.in_query {
+            return None;
+        }
+        loop {
+            let msg = match self.conn.read_msg().await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    self.conn.in_query = false;
+                    return Some(Err(err));
+                }
+            };
+            match protocol::row_msg(msg) {
+                RowMsg::Row(msg) => {
+                    return Some(write::write_row(
+                        &msg,
+                        &self.fields,
+                        &self.conn.session.registry,
+                        sink,
+                    ));
+                }
+                RowMsg::End => {
+                    self.conn.in_query = false;
+                    return None;
+                }
+                RowMsg::Error(err) => {
+                    if let Err(err) = self.conn.skip_to_ready().await {
+                        return Some(Err(err));
+                    }
+                    return Some(Err(err));
+                }
+                RowMsg::Other => {}
+            }
+        }
+    }
+}
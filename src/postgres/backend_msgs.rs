@@ -1,4 +1,6 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
+use crate::cas_err::CasErr;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
@@ -13,6 +15,8 @@ pub enum BackendMsg {
     DataRow,
     // EmptyQueryResponse, // TODO
     ErrorResponse,
+    NoData,
+    NoticeResponse,
     ParameterDescription,
     ParameterStatus,
     ParseComplete,
@@ -29,6 +33,7 @@ pub fn type_of(bytes: &[u8]) -> BackendMsg {
         68 => BackendMsg::DataRow,
         69 => BackendMsg::ErrorResponse,
         75 => BackendMsg::BackendKeyData,
+        78 => BackendMsg::NoticeResponse,
         82 => match bytes[8] {
             0 => BackendMsg::AuthenticationOk,
             3 => BackendMsg::AuthenticationCleartextPassword,
@@ -38,6 +43,7 @@ pub fn type_of(bytes: &[u8]) -> BackendMsg {
         83 => BackendMsg::ParameterStatus,
         84 => BackendMsg::RowDescription,
         90 => BackendMsg::ReadyForQuery,
+        110 => BackendMsg::NoData,
         116 => BackendMsg::ParameterDescription,
         _ => unimplemented!("{}", bytes[0]),
     }
@@ -56,6 +62,15 @@ impl Display for ErrorResponse {
     }
 }
 
+impl From<ErrorResponse> for CasErr {
+    fn from(err: ErrorResponse) -> Self {
+        CasErr::ServerErr {
+            message: err.to_string(),
+            code: err.code,
+        }
+    }
+}
+
 /**
  * Int8 'E'
  * Int32 Length
@@ -85,10 +100,28 @@ pub fn parse_error_response(bytes: &[u8]) -> ErrorResponse {
     }
 }
 
+/**
+ * Int8 'S'
+ * Int32 Length
+ * String Parameter Name
+ * String Parameter Value
+ */
+pub fn parse_parameter_status(bytes: &[u8]) -> (String, String) {
+    let mut rdr = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    // skip tag and message length
+    rdr.skip(5);
+    let name = rdr.c_str();
+    let value = rdr.c_str();
+    (name, value)
+}
+
 #[derive(Clone, Debug)]
 pub struct Field {
     pub name: String,
     pub data_type_oid: i32,
+    // The format the values are sent in, 0 for text or 1 for binary. When a statement is
+    // described, before it is bound, this is always 0.
+    pub format: i16,
 }
 
 /**
@@ -116,22 +149,76 @@ pub fn parse_row_desc(bytes: &[u8]) -> Vec<Field> {
         // skip table_oid (i32) and column (i16)
         rdr.skip(6);
         let data_type_oid = rdr.i32();
-        // skip data_type_size (i16), type_modifier (i32) and format_code (i16)
-        rdr.skip(8);
+        // skip data_type_size (i16) and type_modifier (i32)
+        rdr.skip(6);
+        let format = rdr.i16();
         fields.push({
             Field {
                 name,
                 data_type_oid,
+                format,
             }
         })
     }
     fields
 }
 
-#[derive(Debug)]
+/**
+ * Int8 'D'
+ * Int32 Length
+ * Int16 Number of Values
+ *
+ * Int32 Value Length (NULL is -1)
+ * Bytes Column Value
+ *
+ * For rows from simple queries, where every value is text.
+ */
+pub fn parse_text_row(msg: &[u8]) -> Vec<Option<String>> {
+    let mut rdr = BinaryReader::from(msg, ByteOrder::BigEndian);
+    // skip discriminator and message size
+    rdr.skip(5);
+    let value_count = rdr.i16();
+    let mut values = Vec::with_capacity(value_count as usize);
+    for _ in 0..value_count {
+        let len = rdr.i32();
+        if len == -1 {
+            values.push(None);
+        } else {
+            let bytes = rdr.byte_slice(len as usize);
+            values.push(Some(String::from_utf8_lossy(bytes).into_owned()));
+        }
+    }
+    values
+}
+
+/// A row of pg_type, for a type whose oid isn’t known in advance.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PgType {
-    pub name: String,
     pub oid: i32,
+    pub name: String,
+    // b for base types, c for composites, d for domains, e for enums, m for multiranges, p for
+    // pseudo-types and r for ranges.
+    pub typtype: u8,
+    // The element type of an array, otherwise 0.
+    pub elem: i32,
+    // The type a domain is based on, otherwise 0.
+    pub base_type: i32,
+    // The pg_class row of a composite type, otherwise 0.
+    pub relid: i32,
+    pub namespace: i32,
+    // The element type of a range or multirange, from pg_range, otherwise 0.
+    #[serde(default)]
+    pub range_subtype: i32,
+    // The attributes of a composite type, from pg_attribute, in order, without dropped ones.
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+}
+
+/// A field of a composite type.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attribute {
+    pub name: String,
+    pub type_oid: i32,
 }
 
 /**
@@ -143,21 +230,112 @@ pub struct PgType {
  * Bytes Column Value
  *
  * This specific function will be a subset of DataRow, where the query was
- * SELECT typname, oid FROM pg_types WHERE...
- * so we can assume the result types.
+ * SELECT oid, typname, typtype, typelem, typbasetype, typrelid, typnamespace, range subtype,
+ * attribute names (text[]), attribute types (int4[]) FROM pg_type WHERE...
+ * so we can assume the result types, and that none of them are null.
  */
-pub fn parse_type_lookup_row(msg: &[u8]) -> PgType {
+pub fn parse_type_row(msg: &[u8]) -> PgType {
     let mut rdr = BinaryReader::from(msg, ByteOrder::BigEndian);
-    // skip discriminator, message size and value count (which is always 2)
+    // skip discriminator, message size and value count (which is always 10)
     rdr.skip(7);
 
-    let name_len = rdr.i32();
-    let name_bytes = rdr.byte_slice(name_len as usize);
-    let name = std::str::from_utf8(&name_bytes)
-        .expect("Value will be a valid UTF-8 string.")
-        .to_owned();
     rdr.skip(4); // skip oid length, it’s always 4
     let oid = rdr.i32();
+    let name_len = rdr.i32();
+    let name = String::from_utf8_lossy(rdr.byte_slice(name_len as usize)).into_owned();
+    rdr.skip(4); // a "char" is always 1 byte
+    let typtype = rdr.u8();
+    rdr.skip(4);
+    let elem = rdr.i32();
+    rdr.skip(4);
+    let base_type = rdr.i32();
+    rdr.skip(4);
+    let relid = rdr.i32();
+    rdr.skip(4);
+    let namespace = rdr.i32();
+    rdr.skip(4);
+    let range_subtype = rdr.i32();
+    let names = read_array(&mut rdr, |bytes| {
+        String::from_utf8_lossy(bytes).into_owned()
+    });
+    let type_oids = read_array(&mut rdr, |bytes| {
+        i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    });
+    let attributes = names
+        .into_iter()
+        .zip(type_oids)
+        .map(|(name, type_oid)| Attribute { name, type_oid })
+        .collect();
 
-    PgType { name, oid }
+    PgType {
+        oid,
+        name,
+        typtype,
+        elem,
+        base_type,
+        relid,
+        namespace,
+        range_subtype,
+        attributes,
+    }
+}
+
+/**
+ * Int8 'D'
+ * Int32 Length
+ * Int16 Number of Values, which is 1
+ * Int32 Value Length, which is 4
+ * Int32 Value
+ *
+ * Reads a DataRow with a single int4 value, in binary.
+ */
+/// A row of the query for the names of reg* values: the reg type, the oid, and the name.
+pub fn parse_reg_name_row(msg: &[u8]) -> (i32, u32, String) {
+    let mut rdr = BinaryReader::from(msg, ByteOrder::BigEndian);
+    // skip discriminator, message size and value count (which is always 3)
+    rdr.skip(7);
+    rdr.skip(4);
+    let reg_type = rdr.i32();
+    rdr.skip(4);
+    let oid = rdr.i32() as u32;
+    let name_len = rdr.i32();
+    let name = String::from_utf8_lossy(rdr.byte_slice(name_len as usize)).into_owned();
+    (reg_type, oid, name)
+}
+
+pub fn parse_int4_row(msg: &[u8]) -> i32 {
+    let mut rdr = BinaryReader::from(msg, ByteOrder::BigEndian);
+    rdr.skip(11);
+    rdr.i32()
+}
+
+/**
+ * Int32 Length
+ * Int32 Number of Dimensions
+ * Int32 Has Nulls
+ * Int32 Element Oid
+ * Int32 Dimension Size, if there is a dimension
+ * Int32 Dimension Lower Bound, if there is a dimension
+ * [Int32 Bytes][] Length of Element, Element
+ *
+ * Reads a one-dimensional array without nulls.
+ */
+fn read_array<'a, T, F>(rdr: &mut BinaryReader<'a>, read: F) -> Vec<T>
+where
+    F: Fn(&'a [u8]) -> T,
+{
+    rdr.skip(4);
+    let n_dims = rdr.i32();
+    rdr.skip(8);
+    if n_dims == 0 {
+        return Vec::new();
+    }
+    let count = rdr.i32();
+    rdr.skip(4);
+    (0..count)
+        .map(|_| {
+            let len = rdr.i32();
+            read(rdr.byte_slice(len as usize))
+        })
+        .collect()
 }
@@ -1,184 +1,325 @@
 use crate::args::ConnectionParams;
 use crate::cas_err::CasErr;
-use crate::postgres::backend_msgs;
-use crate::postgres::backend_msgs::BackendMsg;
-use crate::postgres::frontend_msgs;
+use crate::pgpass;
+use crate::postgres::backend_msgs::PgType;
+use crate::postgres::msg_iter;
 use crate::postgres::msg_iter::MsgIter;
 use crate::postgres::output;
-use crate::postgres::postgis::{POSTGIS_TYPES, POSTGIS_TYPE_QUERY};
+use crate::postgres::output::registry::Registry;
+use crate::postgres::output::sink::Sink;
+use crate::postgres::output::value::Rows;
+use crate::postgres::output::write::JsonSink;
+use crate::postgres::params::Param;
+use crate::postgres::protocol;
+use crate::postgres::protocol::{
+    BufferRows, CheckSessionAttrs, Exchange, Query, Session, SimpleQuery, Startup, Step, TypeLookup,
+};
 use crate::postgres::row_iter::RowIter;
-use std::collections::HashMap;
-use std::io::Write;
+use serde::de::DeserializeOwned;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufReader, Write};
 use std::net::TcpStream;
+use std::time::Duration;
+
+pub(super) const DEFAULT_PORT: u16 = 5432;
 
 #[derive(Debug)]
 pub struct Conn {
-    state: ConnectionState,
-    stream: TcpStream,
-    dynamic_types: HashMap<i32, String>,
+    stream: BufReader<TcpStream>,
+    session: Session,
+    // The host, port and database, as host:port/database.
+    server: String,
 }
 
 impl Conn {
+    /// Connects to the first host that accepts the connection and matches the
+    /// target_session_attrs. If none of them do, the error from the last host is returned.
     pub fn connect(params: ConnectionParams) -> Result<Self, CasErr> {
-        let dynamic_types = if params.postgis {
-            HashMap::with_capacity(POSTGIS_TYPES.len())
-        } else {
-            HashMap::new()
-        };
-        let mut conn = Conn {
-            state: ConnectionState::Uninitialised,
-            stream: TcpStream::connect(format!("{}:{}", params.host, params.port.unwrap_or(5432)))?,
-            dynamic_types,
-        };
-
-        let dbname = params.database.as_ref().unwrap_or(&params.user);
-        let password = params.password.as_ref().map(|pd| pd.as_str()).unwrap_or("");
-        conn.send_startup(&params.user, dbname)?;
-        match conn.state {
-            ConnectionState::PasswordRequestedCleartext => {
-                conn.send_password(password)?;
-            }
-            ConnectionState::PasswordRequestedMd5(salt) => {
-                let hashed_pass = md5_password(&params.user, &password, salt);
-                conn.send_password(&hashed_pass)?;
+        let mut hosts = parse_hosts(&params.host, params.port.unwrap_or(DEFAULT_PORT))?;
+        if params.load_balance_hosts {
+            shuffle(&mut hosts);
+        }
+        let mut last_err = None;
+        for (host, port) in hosts {
+            match Conn::connect_host(&params, &host, port) {
+                Ok(conn) => return Ok(conn),
+                Err(err) => last_err = Some(err),
             }
-            ConnectionState::ReadyForQuery => {}
-            ConnectionState::Uninitialised => unreachable!(),
         }
-        if params.postgis {
-            conn.query_postgis_oids()?;
+        Err(last_err.expect("There is always at least one host."))
+    }
+
+    /// The parameters the server reported at startup, such as server_version or TimeZone.
+    pub fn server_param(&self, name: &str) -> Option<&str> {
+        self.session
+            .server_params
+            .get(name)
+            .map(|value| value.as_str())
+    }
+
+    /// The server the connection was made to, as host:port/database.
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// The types that have been looked up in pg_type, because their oids aren’t fixed. They can
+    /// be saved, and loaded into later connections to the same server, to save looking them up.
+    pub fn dynamic_types(&self) -> Vec<PgType> {
+        self.session.registry.dynamic_types()
+    }
+
+    /// Loads types looked up by an earlier connection to the same server. If a query returns a
+    /// type that isn’t among them, they are assumed to be out of date, and are looked up again.
+    pub fn load_types(&mut self, types: Vec<PgType>) {
+        self.session.registry.load_types(types);
+    }
+
+    /// Whether any types have been looked up since the connection was made, so that the types
+    /// saved for it are out of date.
+    pub fn types_looked_up(&self) -> bool {
+        self.session.types_looked_up
+    }
+
+    /// Looks up every type that isn’t built in, other than the row types of tables, which are
+    /// still looked up when they are queried.
+    pub fn look_up_types(&mut self) -> Result<(), CasErr> {
+        self.session.registry.invalidate_cached_types();
+        self.exchange(TypeLookup::user_defined())
+    }
+
+    fn connect_host(params: &ConnectionParams, host: &str, port: u16) -> Result<Self, CasErr> {
+        let dbname = params.database.as_ref().unwrap_or(&params.user);
+        let mut conn = Conn {
+            stream: BufReader::new(TcpStream::connect((host, port))?),
+            session: Session::default(),
+            server: format!("{}:{}/{}", host, port, dbname),
+        };
+        conn.exchange(Startup::new(params, host, port))?;
+        if let Some(reason) = conn.exchange(CheckSessionAttrs::new(params.target_session_attrs))? {
+            return Err(CasErr::PostgresErr(format!(
+                "Rejected server {}:{}: {}",
+                host, port, reason
+            )));
         }
         Ok(conn)
     }
 
+    /// Replaces the serialisers used for the query results, and looks up the oids of the types
+    /// registered by name.
+    pub fn set_registry(&mut self, registry: Registry) -> Result<(), CasErr> {
+        let previous = std::mem::replace(&mut self.session.registry, registry);
+        self.session.registry.inherit_types(previous);
+        let lookup = TypeLookup::by_name(&self.session.registry)?;
+        self.exchange(lookup)
+    }
+
+    /// Runs the query, and writes the rows to out as a JSON array, one row at a time.
     pub fn query<Out>(
         &mut self,
         query: String,
-        params: Vec<String>,
+        params: Vec<Param>,
         out: &mut Out,
     ) -> Result<(), CasErr>
     where
         Out: Write,
     {
-        self.stream.write(&frontend_msgs::parse_msg(&query))?;
-        self.stream.write(&frontend_msgs::describe_msg())?;
-        self.stream.write(&frontend_msgs::bind_msg(
-            params.iter().map(|p| p.as_str()).collect(),
-        ))?;
-        self.stream.write(&frontend_msgs::execute_msg())?;
-        self.stream.write(&frontend_msgs::sync_msg())?;
-        let mut resp = MsgIter::new(&mut self.stream);
-        let rows = RowIter::from(&mut resp)?;
-        output::write::write_rows(rows, &self.dynamic_types, out)
-    }
-
-    fn send_startup(&mut self, user: &str, database: &str) -> Result<(), CasErr> {
-        self.stream
-            .write(&frontend_msgs::startup_msg(user, database, 3, 0))?;
-        let mut msgs = MsgIter::new(&mut self.stream);
-        if let Some(msg) = msgs.next() {
-            match backend_msgs::type_of(&msg) {
-                BackendMsg::AuthenticationCleartextPassword => {
-                    self.state = ConnectionState::PasswordRequestedCleartext;
-                    Ok(())
-                }
-                BackendMsg::AuthenticationMD5Password => {
-                    let salt = [msg[9], msg[10], msg[11], msg[12]];
-                    self.state = ConnectionState::PasswordRequestedMd5(salt);
-                    Ok(())
-                }
-                BackendMsg::ErrorResponse => {
-                    let err_msg = backend_msgs::parse_error_response(&msg);
-                    Err(CasErr::PostgresErr(err_msg.to_string()))
-                }
-                BackendMsg::ReadyForQuery => {
-                    self.state = ConnectionState::ReadyForQuery;
-                    Ok(())
-                }
-                _ => Err(CasErr::PostgresErr(format!(
-                    "Received unexpected message from Postgres: {:?}",
-                    msg
-                ))),
-            }
-        } else {
-            Err(CasErr::PostgresErr(
-                "Expected response, but didn’t receive any.".to_owned(),
-            ))
+        self.query_to(query, params, &mut JsonSink::new(out))
+    }
+
+    /// Runs the query, and passes the rows to the sink, one row at a time.
+    pub fn query_to<S>(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+        sink: &mut S,
+    ) -> Result<(), CasErr>
+    where
+        S: Sink + ?Sized,
+    {
+        let (rows, registry) = self.run_query(&query, &params)?;
+        output::write::write_rows(rows, registry, sink)
+    }
+
+    /// Runs the query, and deserializes each row into a T, without going through JSON. T may be a
+    /// struct with a field for each column, a tuple of the columns, or, if there is only one
+    /// column, the type of that column.
+    pub fn query_as<T>(&mut self, query: String, params: Vec<Param>) -> Result<Vec<T>, CasErr>
+    where
+        T: DeserializeOwned,
+    {
+        let (rows, registry) = self.run_query(&query, &params)?;
+        output::de::deserialize_rows(Rows::new(rows, registry))
+    }
+
+    /// Runs a query, and returns its rows, which are decoded as they are read from the connection.
+    /// They have to be read or dropped before the connection can be used again.
+    pub fn query_rows(&mut self, query: String, params: Vec<Param>) -> Result<Rows<'_>, CasErr> {
+        let (rows, registry) = self.run_query(&query, &params)?;
+        Ok(Rows::new(rows, registry))
+    }
+
+    /// Sends the query, and returns its rows, with the registry to decode them. If the query
+    /// returns records, or reg* values to be named, its whole response is read into memory first,
+    /// and the rows are decoded from there once their field types or names have been looked up.
+    fn run_query(
+        &mut self,
+        query: &str,
+        params: &[Param],
+    ) -> Result<(RowIter<'_>, &Registry), CasErr> {
+        let fields = self.exchange(Query::new(query, params))?;
+        if !self.session.registry.needs_row_pass(&fields) {
+            let rows = RowIter::new(MsgIter::new(&mut self.stream), fields);
+            return Ok((rows, &self.session.registry));
         }
+        let msgs = self.exchange(BufferRows::new(&fields))?;
+        Ok((
+            RowIter::new(msgs.into_iter(), fields),
+            &self.session.registry,
+        ))
     }
 
-    fn send_password(&mut self, password: &str) -> Result<(), CasErr> {
-        self.stream.write(&frontend_msgs::password_msg(password))?;
-        let mut msgs = MsgIter::new(&mut self.stream);
-        while let Some(msg) = msgs.next() {
-            match backend_msgs::type_of(&msg) {
-                BackendMsg::ErrorResponse => {
-                    let err_msg = backend_msgs::parse_error_response(&msg);
-                    Err(CasErr::PostgresErr(err_msg.to_string()))?;
-                }
-                BackendMsg::AuthenticationOk => {}
-                BackendMsg::ParameterStatus => {}
-                BackendMsg::BackendKeyData => {}
-                BackendMsg::ReadyForQuery => {
-                    self.state = ConnectionState::ReadyForQuery;
-                    break;
-                }
-                _ => Err(CasErr::PostgresErr(format!(
-                    "Received unexpected message from Postgres: {:?}",
-                    msg
-                )))?,
+    /// Checks that the connection still works, with the cheapest possible query.
+    pub(super) fn ping(&mut self) -> Result<(), CasErr> {
+        self.exchange(SimpleQuery::new("SELECT 1")).map(|_| ())
+    }
+
+    /// Returns the session to the state of a new connection, rolling back any open transaction and
+    /// discarding settings, prepared statements, temporary tables and so on.
+    pub(super) fn reset_session(&mut self) -> Result<(), CasErr> {
+        match self.exchange(SimpleQuery::new("DISCARD ALL")) {
+            // active_sql_transaction, in_failed_sql_transaction
+            Err(CasErr::ServerErr { code, .. }) if code == "25001" || code == "25P02" => {
+                self.exchange(SimpleQuery::new("ROLLBACK"))?;
+                self.exchange(SimpleQuery::new("DISCARD ALL")).map(|_| ())
             }
+            result => result.map(|_| ()),
         }
+    }
+
+    /// Makes reads and writes on the connection fail once they have waited longer than the
+    /// timeout, or lets them wait indefinitely if it is None.
+    pub(super) fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), CasErr> {
+        let stream = self.stream.get_ref();
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
         Ok(())
     }
 
-    fn query_postgis_oids(&mut self) -> Result<(), CasErr> {
-        self.stream
-            .write(&frontend_msgs::parse_msg(&POSTGIS_TYPE_QUERY))?;
-        self.stream.write(&frontend_msgs::describe_msg())?;
-        self.stream.write(&frontend_msgs::bind_msg(Vec::new()))?;
-        self.stream.write(&frontend_msgs::execute_msg())?;
-        self.stream.write(&frontend_msgs::sync_msg())?;
-
-        let mut resp = MsgIter::new(&mut self.stream);
-        let dynamic_types = &mut self.dynamic_types;
-        while let Some(msg) = resp.next() {
-            match backend_msgs::type_of(&msg) {
-                BackendMsg::ErrorResponse => {
-                    let err_msg = backend_msgs::parse_error_response(&msg);
-                    Err(CasErr::PostgresErr(err_msg.to_string()))?;
-                }
-                BackendMsg::ParseComplete => {}
-                BackendMsg::ParameterDescription => {}
-                BackendMsg::RowDescription => {}
-                BackendMsg::BindComplete => {}
-                BackendMsg::DataRow => {
-                    let pg_type = backend_msgs::parse_type_lookup_row(&msg);
-                    dynamic_types.insert(pg_type.oid, pg_type.name);
-                }
-                BackendMsg::Close => {}
-                BackendMsg::ReadyForQuery => {
-                    break;
-                }
-                _ => {
-                    eprintln!("Received unexpected message from Postgres: {:?}", msg);
+    /// Writes and reads the messages of the exchange, until it is done.
+    fn exchange<E: Exchange>(&mut self, mut exchange: E) -> Result<E::Output, CasErr> {
+        let mut step = exchange.start(&mut self.session)?;
+        loop {
+            match step {
+                Step::Send(msgs) => self
+                    .stream
+                    .get_mut()
+                    .write_all(&msgs)
+                    .map_err(protocol::socket_err)?,
+                Step::Read => {}
+                Step::Done(output) => return Ok(output),
+            }
+            let msg = msg_iter::read_msg(&mut self.stream).map_err(protocol::socket_err)?;
+            step = exchange.handle(&mut self.session, msg)?;
+        }
+    }
+}
+
+/// The password is only looked up in the password file once the server has asked for one, which
+/// is also when libpq reads it. With several hosts, each may have a different password.
+pub(super) fn find_password(
+    params: &ConnectionParams,
+    host: &str,
+    port: u16,
+) -> Result<String, CasErr> {
+    match &params.password {
+        Some(password) => Ok(password.clone()),
+        None => {
+            let dbname = params.database.as_ref().unwrap_or(&params.user);
+            let password = pgpass::find_password(host, port, dbname, &params.user)?;
+            Ok(password.unwrap_or_default())
+        }
+    }
+}
+
+/// Splits a comma-separated list of hosts, each of which may have its own port, e.g.
+/// "db1,db2:5433,[::1]:5434". IPv6 addresses need square brackets if they are given a port.
+pub(super) fn parse_hosts(hosts: &str, default_port: u16) -> Result<Vec<(String, u16)>, CasErr> {
+    hosts
+        .split(',')
+        .map(|host| host.trim())
+        .map(|host| {
+            let (name, port) = if let Some(bracketed) = host.strip_prefix('[') {
+                match bracketed.split_once(']') {
+                    Some((addr, "")) => (addr, None),
+                    Some((addr, port)) => (addr, port.strip_prefix(':')),
+                    None => (host, None),
                 }
+            } else if host.matches(':').count() == 1 {
+                let (name, port) = host.split_once(':').unwrap();
+                (name, Some(port))
+            } else {
+                (host, None)
+            };
+            if name.is_empty() {
+                return Err(CasErr::ArgErr(format!("Invalid host: {:?}", hosts)));
             }
+            let port = match port {
+                Some(port) => port
+                    .parse::<u16>()
+                    .map_err(|_| CasErr::ArgErr(format!("Invalid port for host: {}", host)))?,
+                None => default_port,
+            };
+            Ok((name.to_owned(), port))
+        })
+        .collect()
+}
+
+/// Pairs a comma-separated list of ports with the hosts, as libpq does when it is given more than
+/// one port, e.g. "db1,db2" and "5433,5434" become "db1:5433,db2:5434". An empty port leaves its
+/// host on the default port.
+pub(crate) fn join_host_ports(hosts: &str, ports: &str) -> Result<String, String> {
+    let hosts: Vec<&str> = hosts.split(',').map(|host| host.trim()).collect();
+    let ports: Vec<&str> = ports.split(',').map(|port| port.trim()).collect();
+    if hosts.len() != ports.len() {
+        return Err(format!(
+            "{} ports were given for {} hosts",
+            ports.len(),
+            hosts.len()
+        ));
+    }
+    let mut joined = Vec::with_capacity(hosts.len());
+    for (host, port) in hosts.into_iter().zip(ports) {
+        if port.is_empty() {
+            joined.push(host.to_owned());
+            continue;
+        }
+        port.parse::<u16>()
+            .map_err(|_| format!("Invalid port {}", port))?;
+        if host.contains(':') {
+            joined.push(format!("[{}]:{}", host, port));
+        } else {
+            joined.push(format!("{}:{}", host, port));
         }
-        Ok(())
     }
+    Ok(joined.join(","))
 }
 
-#[derive(Debug)]
-enum ConnectionState {
-    PasswordRequestedCleartext,
-    PasswordRequestedMd5([u8; 4]),
-    ReadyForQuery,
-    Uninitialised,
+/// Shuffles the hosts for load balancing. It doesn’t need to be a good random number generator, so
+/// rather than adding a dependency, this uses the random seed from std’s HashMap.
+pub(super) fn shuffle<T>(items: &mut [T]) {
+    let mut seed = RandomState::new().build_hasher().finish() | 1;
+    for idx in (1..items.len()).rev() {
+        // xorshift64
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        items.swap(idx, (seed % (idx as u64 + 1)) as usize);
+    }
 }
 
 // Directly borrowed from rust-postgres (https://github.com/sfackler/rust-postgres).
-fn md5_password(user: &str, password: &str, salt: [u8; 4]) -> String {
+pub(super) fn md5_password(user: &str, password: &str, salt: [u8; 4]) -> String {
     let mut context = md5::Context::new();
     context.consume(password);
     context.consume(user);
@@ -191,7 +332,7 @@ fn md5_password(user: &str, password: &str, salt: [u8; 4]) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::postgres::connection::md5_password;
+    use crate::postgres::connection::{join_host_ports, md5_password, parse_hosts};
 
     #[test]
     fn test_md5() {
@@ -200,4 +341,29 @@ mod tests {
             md5_password("michael", "cascat", [0x81, 0x4F, 0xA3, 0x5A])
         );
     }
+
+    #[test]
+    fn test_parse_hosts() {
+        assert_eq!(
+            parse_hosts("db1, db2:5433,[::1]:5434,::1", 5432).unwrap(),
+            vec![
+                ("db1".to_owned(), 5432),
+                ("db2".to_owned(), 5433),
+                ("::1".to_owned(), 5434),
+                ("::1".to_owned(), 5432),
+            ]
+        );
+        assert!(parse_hosts("db1,", 5432).is_err());
+        assert!(parse_hosts("db1:port", 5432).is_err());
+    }
+
+    #[test]
+    fn test_join_host_ports() {
+        assert_eq!(
+            join_host_ports("db1, db2,::1", "5433,,5434").unwrap(),
+            "db1:5433,db2,[::1]:5434"
+        );
+        assert!(join_host_ports("db1,db2", "5433,5434,5435").is_err());
+        assert!(join_host_ports("db1,db2", "5433,high").is_err());
+    }
 }
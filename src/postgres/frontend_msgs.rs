@@ -1,3 +1,5 @@
+use crate::postgres::params::Param;
+
 /**
  * Int32 Length
  * Int16 Major Protocol Version
@@ -6,21 +8,30 @@
  * Null Final Byte
  *
  * For simple use cases, only the user and database parameters are relevant, so
- * I’m skipping the replication mode parameter.
+ * I’m skipping the replication mode parameter. Any other settings, such as
+ * default_transaction_read_only, can be passed in as run-time parameters.
  */
-pub fn startup_msg(user: &str, database: &str, major_version: i16, minor_version: i16) -> Vec<u8> {
-    build_msg(
-        None,
-        vec![
-            Element::Int16(major_version),
-            Element::Int16(minor_version),
-            Element::CStr("user"),
-            Element::CStr(user),
-            Element::CStr("database"),
-            Element::CStr(database),
-            Element::Byte(0),
-        ],
-    )
+pub fn startup_msg(
+    user: &str,
+    database: &str,
+    settings: &[(&str, &str)],
+    major_version: i16,
+    minor_version: i16,
+) -> Vec<u8> {
+    let mut msg_elements = vec![
+        Element::Int16(major_version),
+        Element::Int16(minor_version),
+        Element::CStr("user"),
+        Element::CStr(user),
+        Element::CStr("database"),
+        Element::CStr(database),
+    ];
+    for (key, value) in settings {
+        msg_elements.push(Element::CStr(key));
+        msg_elements.push(Element::CStr(value));
+    }
+    msg_elements.push(Element::Byte(0));
+    build_msg(None, msg_elements)
 }
 
 /**
@@ -32,6 +43,18 @@ pub fn password_msg(password: &str) -> Vec<u8> {
     build_msg(Some('p'), vec![Element::CStr(password)])
 }
 
+/**
+ * Int8 'Q'
+ * Int32 Length
+ * CString Query String
+ *
+ * A simple query, which returns its results as text. casql only uses it internally, for small
+ * queries about the server, where the extended protocol would be overkill.
+ */
+pub fn query_msg(query: &str) -> Vec<u8> {
+    build_msg(Some('Q'), vec![Element::CStr(query)])
+}
+
 /**
 * Int8 'P'
 * Int32 Length
@@ -40,17 +63,22 @@ pub fn password_msg(password: &str) -> Vec<u8> {
 * Int16 Number of Specified Types
 * Int32[] Type Oids
 
- Specifying types doesn’t apply to casql, since queries will either have values passed in unescaped,
- or passed in as text. In most cases, Postgres will be able to figure them out anyway.
+ Text params are given the type 0, which leaves Postgres to figure them out. Binary params have to
+ be given their type, because Postgres can’t guess it from the bytes.
 
  It also isn’t persisting any prepared statements, so it will always use the anonymous prepared
  statement that is parsed and immediately used.
  */
-pub fn parse_msg(query: &str) -> Vec<u8> {
-    build_msg(
-        Some('P'),
-        vec![Element::CStr(""), Element::CStr(query), Element::Int16(0)],
-    )
+pub fn parse_msg(query: &str, params: &[Param]) -> Vec<u8> {
+    let mut msg_elements = vec![
+        Element::CStr(""),
+        Element::CStr(query),
+        Element::Int16(params.len() as i16),
+    ];
+    for param in params {
+        msg_elements.push(Element::Int32(param.type_oid));
+    }
+    build_msg(Some('P'), msg_elements)
 }
 
 /**
@@ -74,27 +102,37 @@ pub fn describe_msg() -> Vec<u8> {
 * Int16 Number of Param Format Codes (n)
 * Int16[] n Format Codes
 * Int16 Number of Param Values
-* [Int32 Bytes][] Length of Param Value, Param Value
+* [Int32 Bytes][] Length of Param Value, Param Value, -1 and no value for null
 * Int16 Number of Result Format Codes (k)
 * Int16[] k Format Codes
 
-  Destination portal and statement are always anonymous. All params will be formatted as text, so
-  we just specify it once. All results will be formatted as binary, so also only specified once.
+  Destination portal and statement are always anonymous. Each param has its own format code, 0 for
+  text or 1 for binary. Results are formatted as binary, except for the columns whose types casql
+  can’t decode, which are formatted as text.
  */
-pub fn bind_msg(params: Vec<&str>) -> Vec<u8> {
+pub fn bind_msg(params: &[Param], result_formats: &[i16]) -> Vec<u8> {
     let mut msg_elements = vec![
         Element::CStr(""),
         Element::CStr(""),
-        Element::Int16(1),
-        Element::Int16(0), // 0 for text params
         Element::Int16(params.len() as i16),
     ];
-    params.iter().for_each(|param| {
-        msg_elements.push(Element::Int32(param.len() as i32));
-        msg_elements.push(Element::Str(param));
-    });
-    msg_elements.push(Element::Int16(1));
-    msg_elements.push(Element::Int16(1)); // 1 for binary results
+    for param in params {
+        msg_elements.push(Element::Int16(param.format));
+    }
+    msg_elements.push(Element::Int16(params.len() as i16));
+    for param in params {
+        match &param.value {
+            Some(value) => {
+                msg_elements.push(Element::Int32(value.len() as i32));
+                msg_elements.push(Element::Bytes(value));
+            }
+            None => msg_elements.push(Element::Int32(-1)),
+        }
+    }
+    msg_elements.push(Element::Int16(result_formats.len() as i16));
+    for format in result_formats {
+        msg_elements.push(Element::Int16(*format));
+    }
     build_msg(Some('B'), msg_elements)
 }
 
@@ -125,7 +163,7 @@ fn build_msg(msg_tag: Option<char>, elements: Vec<Element>) -> Vec<u8> {
             Element::Byte(_) => 1,
             Element::Int16(_) => 2,
             Element::Int32(_) => 4,
-            Element::Str(str) => str.len(),
+            Element::Bytes(bytes) => bytes.len(),
             Element::CStr(str) => str.len() + 1,
         }
     });
@@ -138,7 +176,7 @@ fn build_msg(msg_tag: Option<char>, elements: Vec<Element>) -> Vec<u8> {
         Element::Byte(byte) => msg_bytes.push(*byte),
         Element::Int16(int) => msg_bytes.extend_from_slice(&int.to_be_bytes()),
         Element::Int32(int) => msg_bytes.extend_from_slice(&int.to_be_bytes()),
-        Element::Str(str) => msg_bytes.extend_from_slice(str.as_bytes()),
+        Element::Bytes(bytes) => msg_bytes.extend_from_slice(bytes),
         Element::CStr(str) => {
             msg_bytes.extend_from_slice(str.as_bytes());
             msg_bytes.push(0);
@@ -151,6 +189,6 @@ enum Element<'a> {
     Byte(u8),
     Int16(i16),
     Int32(i32),
-    Str(&'a str),
+    Bytes(&'a [u8]),
     CStr(&'a str),
 }
@@ -1,7 +1,18 @@
+#[cfg(feature = "tokio")]
+pub mod async_connection;
 mod backend_msgs;
 pub mod connection;
 mod frontend_msgs;
 mod msg_iter;
 mod output;
-mod postgis;
+pub mod params;
+pub mod pool;
+mod protocol;
 mod row_iter;
+
+pub use backend_msgs::PgType;
+pub use output::registry::{RegTypeFormat, Registry, Serialiser};
+pub use output::sink::Sink;
+pub use output::text_search::TextSearchFormat;
+pub use output::value::{Row, Rows, Value};
+pub use output::write::JsonSink;
@@ -1,60 +1,36 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
-use std::io::Write;
-
-const DOUBLE_QUOTE: &[u8] = "\"".as_bytes();
-const LEFT_SQUARE: &[u8] = "[".as_bytes();
-const RIGHT_SQUARE: &[u8] = "]".as_bytes();
-const COMMA: &[u8] = ",".as_bytes();
+use crate::postgres::output::value::Value;
 
 /// Given:
 /// i32: # of bits in string
 /// u8[]: bytes representing octets, the last of which may only represent a partial octet,
 ///       e.g. 64 (10000000) may represent "1" if there’s only one bit remaining
-/// Writes:
-/// a string composed of 1s and 0s
-pub fn serialise_bitstring<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a BitString composed of 1s and 0s
+pub fn decode_bitstring(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut bit_str = BinaryReader::from(bytes, ByteOrder::BigEndian);
     let mut bit_len = bit_str.i32();
-    out.write(DOUBLE_QUOTE)?;
+    let mut bits = String::with_capacity(bit_len as usize);
     while bit_len >= 8 {
         let block = bit_str.u8();
-        write!(out, "{:08b}", block)?;
+        bits.push_str(&format!("{:08b}", block));
         bit_len -= 8;
     }
     if bit_len > 0 {
         let block = bit_str.u8();
-        let bits = format!("{:08b}", block);
-        let trimmed = &bits.as_bytes()[0..(bit_len as usize)];
-        out.write(trimmed)?;
+        let block_bits = format!("{:08b}", block);
+        bits.push_str(&block_bits[0..(bit_len as usize)]);
     }
-    out.write(DOUBLE_QUOTE)?;
-    Ok(())
+    Ok(Value::BitString(bits))
 }
 
 /// Given:
 /// u8[]: bytes
-/// Writes:
-/// an array of decimal numbers
-pub fn serialise_bytes<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
-    out.write(LEFT_SQUARE)?;
-    let mut first = true;
-    for byte in bytes {
-        if first {
-            first = false;
-        } else {
-            out.write(COMMA)?;
-        }
-        itoap::write(&mut (*out), *byte)?;
-    }
-    out.write(RIGHT_SQUARE)?;
-    Ok(())
+/// Returns:
+/// the Bytes
+pub fn decode_bytes(bytes: &[u8]) -> Result<Value, CasErr> {
+    Ok(Value::Bytes(bytes.to_vec()))
 }
 
 /// From https://datatracker.ietf.org/doc/html/rfc4122#section-4.1.2
@@ -66,16 +42,12 @@ where
 /// u8: the low field of the clock sequence
 /// u48: the spatially unique node identifier
 ///
-/// Writes:
+/// Returns:
 /// a lowercase UUID string
-pub fn serialise_uuid<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+pub fn decode_uuid(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut uuid = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(
-        out,
-        "\"{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:04x}{:08x}\"",
+    Ok(Value::Uuid(format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:04x}{:08x}",
         uuid.i32(),
         uuid.i16(),
         uuid.i16(),
@@ -83,6 +55,5 @@ where
         uuid.u8(),
         uuid.i16(),
         uuid.i32()
-    )?;
-    Ok(())
+    )))
 }
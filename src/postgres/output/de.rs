@@ -0,0 +1,429 @@
+use crate::cas_err::CasErr;
+use crate::postgres::output::value::{Row, Rows, Value};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Unexpected,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::sync::Arc;
+
+/// Deserializes each row into a T. If a row fails to deserialize, the rest of the rows are read
+/// and discarded when the Rows are dropped, so that the connection can be used again.
+pub fn deserialize_rows<T>(rows: Rows) -> Result<Vec<T>, CasErr>
+where
+    T: DeserializeOwned,
+{
+    rows.map(|row| T::deserialize(RowDeserializer::from(row?)))
+        .collect()
+}
+
+/// A Row, which deserializes as a map of column names to values, so that it can be decoded into a
+/// struct, or as a sequence of values, for a tuple. If there is only one column, the row can also
+/// be deserialized as that column’s value.
+pub struct RowDeserializer {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl From<Row> for RowDeserializer {
+    fn from(row: Row) -> Self {
+        RowDeserializer {
+            columns: row.columns,
+            values: row.values,
+        }
+    }
+}
+
+impl RowDeserializer {
+    fn single_value(mut self) -> Result<Value, CasErr> {
+        if self.values.len() == 1 {
+            Ok(self.values.remove(0))
+        } else {
+            Err(CasErr::DecodeErr(format!(
+                "Expected a single column, but the row has {}",
+                self.values.len()
+            )))
+        }
+    }
+}
+
+macro_rules! forward_to_single_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, CasErr>
+            where
+                V: Visitor<'de>,
+            {
+                self.single_value()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer {
+    type Error = CasErr;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(RowMap {
+            columns: self.columns,
+            idx: 0,
+            values: self.values.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(SeqDeserializer::new(self.values.into_iter()))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        self.single_value()?
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_single_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_option
+    }
+
+    forward_to_deserialize_any! {
+        unit unit_struct map struct identifier ignored_any
+    }
+}
+
+struct RowMap {
+    columns: Arc<[String]>,
+    idx: usize,
+    values: std::vec::IntoIter<Value>,
+    value: Option<Value>,
+}
+
+impl<'de> MapAccess<'de> for RowMap {
+    type Error = CasErr;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, CasErr>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match (self.columns.get(self.idx), self.values.next()) {
+            (Some(column), Some(value)) => {
+                self.idx += 1;
+                self.value = Some(value);
+                seed.deserialize(column.as_str().into_deserializer())
+                    .map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, CasErr>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(CasErr::DecodeErr(
+                "Value requested before its key.".to_owned(),
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+/// Numbers, bools, strings, bytes and arrays are passed to the visitor directly, points and the
+/// other shapes as sequences of numbers, and ranges and composites as maps. Everything else is
+/// passed as the string it would be written as in the JSON output, without the quotes, e.g. dates,
+/// UUIDs and numerics. Numerics, and numbers written as strings, like xid8s, can also be
+/// deserialized as integers or floats, if they fit.
+impl Value {
+    /// The value as a string, if it is written as one, or is a number or bool. Other values are
+    /// returned as they are, to be deserialized as sequences or maps.
+    fn into_text(self) -> Result<String, Value> {
+        match self {
+            Value::Numeric(text)
+            | Value::Text(text)
+            | Value::BitString(text)
+            | Value::Date(text)
+            | Value::Time(text)
+            | Value::Timestamp(text)
+            | Value::Interval(text)
+            | Value::Uuid(text)
+            | Value::Inet(text)
+            | Value::MacAddr(text)
+            | Value::Json(text)
+            | Value::Geometry(text) => Ok(text),
+            Value::Bool(bool) => Ok(bool.to_string()),
+            Value::Int(int) => Ok(int.to_string()),
+            Value::Float(float) => Ok(float.to_string()),
+            Value::Float32(float) => Ok(float.to_string()),
+            Value::EmptyRange => Ok("empty".to_string()),
+            Value::Line(a, b, c) => Ok(format!("{}x + {}y + {} = 0", a, b, c)),
+            value => Err(value),
+        }
+    }
+
+    fn deserialize_text<'de, V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self.into_text() {
+            Ok(text) => visitor.visit_string(text),
+            // deserialize_any handles every value that isn’t text before falling back to this.
+            Err(value) => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_number<'de, V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Numeric(text) | Value::Text(text) => {
+                if let Ok(int) = text.parse::<i64>() {
+                    visitor.visit_i64(int)
+                } else if let Ok(int) = text.parse::<u64>() {
+                    visitor.visit_u64(int)
+                } else {
+                    Err(serde::de::Error::invalid_value(
+                        Unexpected::Str(&text),
+                        &visitor,
+                    ))
+                }
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_float<'de, V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Numeric(text) | Value::Text(text) => match text.parse::<f64>() {
+                Ok(float) => visitor.visit_f64(float),
+                Err(_) => Err(serde::de::Error::invalid_value(
+                    Unexpected::Str(&text),
+                    &visitor,
+                )),
+            },
+            value => value.deserialize_any(visitor),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, CasErr> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = CasErr;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            Value::Bool(bool) => visitor.visit_bool(bool),
+            Value::Int(int) => visitor.visit_i64(int),
+            Value::Float(float) => visitor.visit_f64(float),
+            Value::Float32(float) => visitor.visit_f32(float),
+            Value::Bytes(bytes) | Value::Unknown(bytes) => visitor.visit_byte_buf(bytes),
+            Value::Tid(block, offset) => visitor.visit_seq(SeqDeserializer::new(
+                vec![Value::Int(block as i64), Value::Int(offset as i64)].into_iter(),
+            )),
+            Value::Point(x, y) => visitor.visit_seq(SeqDeserializer::new(
+                vec![Value::Float(x), Value::Float(y)].into_iter(),
+            )),
+            Value::Points(points) => visitor.visit_seq(SeqDeserializer::new(
+                points.into_iter().map(|(x, y)| Value::Point(x, y)),
+            )),
+            Value::Circle(x, y, radius) => visitor.visit_seq(SeqDeserializer::new(
+                vec![Value::Point(x, y), Value::Float(radius)].into_iter(),
+            )),
+            Value::Array(elements) => visitor.visit_seq(SeqDeserializer::new(elements.into_iter())),
+            Value::Range {
+                lower,
+                upper,
+                lower_inc,
+                upper_inc,
+            } => visitor.visit_map(MapDeserializer::new(
+                vec![
+                    ("lower", *lower),
+                    ("upper", *upper),
+                    ("lower_inc", Value::Bool(lower_inc)),
+                    ("upper_inc", Value::Bool(upper_inc)),
+                ]
+                .into_iter(),
+            )),
+            Value::Record(fields) => visitor.visit_map(MapDeserializer::new(fields.into_iter())),
+            value => value.deserialize_text(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            value => value.deserialize_text(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            // For Vec<u8>, which serde deserializes as a sequence.
+            Value::Bytes(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.into_iter())),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            value => match value.into_text() {
+                Ok(text) => visitor.visit_enum(text.into_deserializer()),
+                Err(value) => value.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, CasErr>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_number(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_float(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, CasErr> {
+        self.deserialize_float(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool char bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
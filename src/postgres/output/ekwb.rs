@@ -1,200 +1,494 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
+use crate::postgres::output::value::Value;
 use std::io::Write;
 
+// Geographies are in WGS 84 unless they were given another SRID.
+const GEOGRAPHY_SRID: i32 = 4326;
+
 const LEFT_SQUARE: &[u8] = "[".as_bytes();
 const RIGHT_SQUARE: &[u8] = "]".as_bytes();
 const COMMA: &[u8] = ",".as_bytes();
 
+/// Returns:
+/// a Geometry, holding the Geojson written by serialise_geom
+pub fn decode_geom(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut out = Vec::new();
+    serialise_geom(bytes, &mut out)?;
+    let geojson = String::from_utf8(out).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Geometry(geojson))
+}
+
+/// Returns:
+/// a Geometry, like decode_geom, but with a crs of EPSG:4326 if the geography has no SRID
+pub fn decode_geography(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut out = Vec::new();
+    write_geom(bytes, Some(GEOGRAPHY_SRID), &mut out)?;
+    let geojson = String::from_utf8(out).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Geometry(geojson))
+}
+
+/// box2d has no binary format, so it is decoded from its text format.
+///
+/// Given:
+/// BOX(minx miny,maxx maxy)
+///
+/// Returns:
+/// an Array of [minx,miny,maxx,maxy], like a GeoJSON bbox
+pub fn decode_box2d(bytes: &[u8]) -> Result<Value, CasErr> {
+    decode_bbox(bytes, "BOX", 2)
+}
+
+/// box3d has no binary format, so it is decoded from its text format.
+///
 /// Given:
-/// u8: byte order
-/// i32: the least significant byte is the geometry type, the most is the coordinate type, the
-///      middle two bytes appear to always be empty
+/// BOX3D(minx miny minz,maxx maxy maxz)
+///
+/// Returns:
+/// an Array of [minx,miny,minz,maxx,maxy,maxz], like a GeoJSON bbox
+pub fn decode_box3d(bytes: &[u8]) -> Result<Value, CasErr> {
+    decode_bbox(bytes, "BOX3D", 3)
+}
+
+fn decode_bbox(bytes: &[u8], prefix: &str, n_dims: usize) -> Result<Value, CasErr> {
+    let text = std::str::from_utf8(bytes).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    let invalid = || CasErr::DecodeErr(format!("Invalid {}: {}", prefix, text));
+    let corners = text
+        .strip_prefix(prefix)
+        .and_then(|s| s.strip_prefix('('))
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(invalid)?;
+    let mut bbox = Vec::with_capacity(n_dims * 2);
+    for corner in corners.split(',') {
+        for coord in corner.split_whitespace() {
+            bbox.push(Value::Float(coord.parse::<f64>().map_err(|_| invalid())?));
+        }
+    }
+    if bbox.len() != n_dims * 2 {
+        return Err(invalid());
+    }
+    Ok(Value::Array(bbox))
+}
+
+/// Given:
+/// u8: byte order, 0 for big endian and 1 for little endian
+/// u32: the geometry type, in the lower bits, with flags in the top byte for whether it has Z or M
+///      coordinates, and whether it has an SRID
 /// Option<i32>: an SRID if it was specified at insert time
-/// f64[]: coordinates, TODO: document the formats by type
+/// ...: the geometry, which depends on its type:
+///   Point: f64[], a coordinate for each dimension, all NaN if the point is empty
+///   LineString: u32 number of points, then each point
+///   Polygon: u32 number of rings, then each ring, as a number of points and each point
+///   Multi* and GeometryCollection: u32 number of geometries, then each one, with its own byte
+///   order and type, but without an SRID
 ///
 /// Writes:
 /// A Geojson object. If an SRID exists it will always be included, unlike ST_AsGeoJson, which skips
-/// it if it’s 4326. Also, it includes M coordinates if present.
+/// it if it’s 4326. Also, it includes M coordinates if present. Empty geometries have empty
+/// coordinates, or geometries for an empty collection.
 ///
 pub fn serialise_geom<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
 where
     Out: Write,
 {
-    let byte_order = if bytes[0] == 0 {
-        ByteOrder::BigEndian
-    } else {
-        ByteOrder::LittleEndian
+    write_geom(bytes, None, out)
+}
+
+fn write_geom<Out>(bytes: &[u8], default_srid: Option<i32>, out: &mut Out) -> Result<(), CasErr>
+where
+    Out: Write,
+{
+    // The byte order is set from the first byte when the header is read.
+    let mut rdr = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let header = read_header(&mut rdr)?;
+    write_geom_object(&mut rdr, &header, header.srid.or(default_srid), out)
+}
+
+struct Header {
+    geom_type: u32,
+    coord_dims: usize,
+    srid: Option<i32>,
+}
+
+fn read_header(rdr: &mut BinaryReader) -> Result<Header, CasErr> {
+    let byte_order = match rdr.u8() {
+        0 => ByteOrder::BigEndian,
+        1 => ByteOrder::LittleEndian,
+        byte => {
+            return Err(CasErr::DecodeErr(format!(
+                "Invalid EWKB byte order: {}",
+                byte
+            )))
+        }
     };
-    let mut rdr = BinaryReader::from(bytes, byte_order);
-    // skip byte_order (u8)
-    rdr.skip(1);
-    let geom_type = rdr.u8();
-    rdr.skip(2);
-    let coord_type = rdr.u8();
-    let srid = if has_srid(coord_type) {
+    rdr.set_order(byte_order);
+    let type_int = rdr.i32() as u32;
+    let coord_type = (type_int >> 24) as u8;
+    let srid = if has_srid(coord_type)? {
         Some(rdr.i32())
     } else {
         None
     };
-    let coord_dims = coord_size(coord_type);
-    write!(out, "{{\"type\":\"{}\",", geom_name(geom_type))?;
+    Ok(Header {
+        geom_type: type_int & 0x00FF_FFFF,
+        coord_dims: coord_size(coord_type)?,
+        srid,
+    })
+}
+
+/// Writes a geometry whose header has been read. Only the outermost one is given a crs.
+fn write_geom_object<Out>(
+    rdr: &mut BinaryReader,
+    header: &Header,
+    srid: Option<i32>,
+    out: &mut Out,
+) -> Result<(), CasErr>
+where
+    Out: Write,
+{
+    write!(out, "{{\"type\":\"{}\",", geom_name(header.geom_type)?)?;
     if let Some(srid) = srid {
-        write!(out, "\"crs\":{{\"type\":\"name\",\"properties\":{{\"name\":\"EPSG:{}\"}}}},", srid)?;
+        write!(
+            out,
+            "\"crs\":{{\"type\":\"name\",\"properties\":{{\"name\":\"EPSG:{}\"}}}},",
+            srid
+        )?;
     }
-    match geom_type {
-        1 => {
-            // Point
-            write!(out, "\"coordinates\":")?;
-            write_coords(&mut rdr, 1, coord_dims, out)?;
-        }
-        2 => {
-            // Linestring
-            write!(out, "\"coordinates\":")?;
-            write_coords(&mut rdr, 2, coord_dims, out)?;
-        }
-        3 => {
-            // Polygon
-            write!(out, "\"coordinates\":")?;
-            write_coords(&mut rdr, 3, coord_dims, out)?;
-        }
-        4 => {
-            // Multipoint
-            write!(out, "\"coordinates\":")?;
-            write_coords_array(&mut rdr, out)?;
-        }
-        5 => {
-            // Multilinestring
-            write!(out, "\"coordinates\":")?;
-            write_coords_array(&mut rdr, out)?;
-        }
-        6 => {
-            // Multipolygon
-            write!(out, "\"coordinates\":")?;
-            write_coords_array(&mut rdr, out)?;
-        }
-        7 => {
-            // Geometry Collection {
-            write!(out, "\"geometries\":")?;
-            write_collection(&mut rdr, out)?;
-        }
-        _ => {
-            unimplemented!("{}", geom_type)
-        }
+    if header.geom_type == 7 {
+        write!(out, "\"geometries\":")?;
+        write_collection(rdr, out)?;
+    } else {
+        write!(out, "\"coordinates\":")?;
+        write_coords(rdr, header, out)?;
     }
     write!(out, "}}")?;
     Ok(())
 }
 
-fn geom_name(geom_type: u8) -> &'static str {
+fn geom_name(geom_type: u32) -> Result<&'static str, CasErr> {
     match geom_type {
-        1 => "Point",
-        2 => "LineString",
-        3 => "Polygon",
-        4 => "MultiPoint",
-        5 => "MultiLineString",
-        6 => "MultiPolygon",
-        7 => "GeometryCollection",
-        _ => unimplemented!("{}", geom_type)
+        1 => Ok("Point"),
+        2 => Ok("LineString"),
+        3 => Ok("Polygon"),
+        4 => Ok("MultiPoint"),
+        5 => Ok("MultiLineString"),
+        6 => Ok("MultiPolygon"),
+        7 => Ok("GeometryCollection"),
+        // Curves, triangles and the other types that aren’t in GeoJSON.
+        _ => Err(CasErr::DecodeErr(format!(
+            "Unsupported geometry type: {}",
+            geom_type
+        ))),
     }
 }
 
-fn write_coords_array<Out>(rdr: &mut BinaryReader, out: &mut Out) -> Result<(), CasErr>
+fn write_coords<Out>(rdr: &mut BinaryReader, header: &Header, out: &mut Out) -> Result<(), CasErr>
 where
     Out: Write,
 {
-    let collection_size = rdr.i32();
-    let mut first = true;
-    out.write(LEFT_SQUARE)?;
-    for _ in 0..collection_size {
-        if first {
-            first = false;
-        } else {
-            out.write(COMMA)?;
-        }
-        // Not sure what his flag is, it has always been 1 so far.
-        let _flag = rdr.u8();
-        let geom_type = rdr.u8();
-        rdr.skip(2);
-        let coord_type = rdr.u8();
-        let n_dims = geom_type; // Point (1) -> 1, Line (2) -> 2, etc.
-        write_coords(rdr, n_dims as i32, coord_size(coord_type), out)?;
-    }
-    out.write(RIGHT_SQUARE)?;
-    Ok(())
+    match header.geom_type {
+        1 => write_point(rdr, header.coord_dims, out),
+        2 => write_array(rdr, out, |rdr, out| {
+            write_point(rdr, header.coord_dims, out)
+        }),
+        3 => write_array(rdr, out, |rdr, out| {
+            write_array(rdr, out, |rdr, out| {
+                write_point(rdr, header.coord_dims, out)
+            })
+        }),
+        // Each geometry in a Multi* has its own header, so it can have a different byte order.
+        4..=6 => write_array(rdr, out, |rdr, out| {
+            let child = read_header(rdr)?;
+            if child.geom_type != header.geom_type - 3 {
+                return Err(CasErr::DecodeErr(format!(
+                    "{} contains a {}",
+                    geom_name(header.geom_type)?,
+                    geom_name(child.geom_type)?
+                )));
+            }
+            write_coords(rdr, &child, out)
+        }),
+        _ => Err(CasErr::DecodeErr(format!(
+            "{} has no coordinates",
+            geom_name(header.geom_type)?
+        ))),
+    }
 }
 
-fn write_coords<Out>(
-    bytes: &mut BinaryReader,
-    n_dims: i32,
-    point_n_dims: usize,
-    out: &mut Out,
-) -> Result<(), CasErr>
+/// Writes the geometries in a collection, which can include other collections.
+fn write_collection<Out>(rdr: &mut BinaryReader, out: &mut Out) -> Result<(), CasErr>
 where
     Out: Write,
 {
-    if n_dims == 1 {
-        let mut first = true;
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..point_n_dims {
-            if first {
-                first = false
-            } else {
-                out.write(COMMA)?;
-            }
-            write!(out, "{}", bytes.f64())?;
-        }
-        out.write(RIGHT_SQUARE)?;
-    } else {
-        let mut first = true;
-        // Unlike Postgres arrays, the sub-dimensions can be different lengths, so each line within
-        // a polygon begins with its own length.
-        let len = bytes.i32();
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..len {
-            if first {
-                first = false
-            } else {
-                out.write(COMMA)?;
+    write_array(rdr, out, |rdr, out| {
+        let child = read_header(rdr)?;
+        write_geom_object(rdr, &child, None, out)
+    })
+}
+
+/// An empty point has NaN for each coordinate, and is written as an empty array.
+fn write_point<Out>(rdr: &mut BinaryReader, coord_dims: usize, out: &mut Out) -> Result<(), CasErr>
+where
+    Out: Write,
+{
+    let coords: Vec<f64> = (0..coord_dims).map(|_| rdr.f64()).collect();
+    out.write_all(LEFT_SQUARE)?;
+    if !coords.iter().all(|coord| coord.is_nan()) {
+        for (idx, coord) in coords.iter().enumerate() {
+            if idx > 0 {
+                out.write_all(COMMA)?;
             }
-            write_coords(bytes, n_dims - 1, point_n_dims, out)?;
+            write!(out, "{}", coord)?;
         }
-        out.write(RIGHT_SQUARE)?;
     }
+    out.write_all(RIGHT_SQUARE)?;
     Ok(())
 }
 
-fn write_collection<Out>(_bytes: &mut BinaryReader, _out: &mut Out) -> Result<(), CasErr>
-    where
-        Out: Write,
+/// Writes an array of items that begins with its length, like the points in a line, or the rings
+/// in a polygon. Unlike Postgres arrays, the items can be different lengths, so each begins with
+/// its own.
+fn write_array<Out, F>(rdr: &mut BinaryReader, out: &mut Out, write_item: F) -> Result<(), CasErr>
+where
+    Out: Write,
+    F: Fn(&mut BinaryReader, &mut Out) -> Result<(), CasErr>,
 {
-   todo!()
+    let len = rdr.i32();
+    out.write_all(LEFT_SQUARE)?;
+    for idx in 0..len {
+        if idx > 0 {
+            out.write_all(COMMA)?;
+        }
+        write_item(rdr, out)?;
+    }
+    out.write_all(RIGHT_SQUARE)?;
+    Ok(())
 }
 
-fn has_srid(flag: u8) -> bool {
+fn has_srid(flag: u8) -> Result<bool, CasErr> {
     match flag {
-        0x00 => false,
-        0x20 => true,
-        0x40 => false,
-        0x60 => true,
-        0x80 => false,
-        0xA0 => true,
-        0xC0 => false,
-        0xE0 => true,
-        _ => unreachable!(),
+        0x00 => Ok(false),
+        0x20 => Ok(true),
+        0x40 => Ok(false),
+        0x60 => Ok(true),
+        0x80 => Ok(false),
+        0xA0 => Ok(true),
+        0xC0 => Ok(false),
+        0xE0 => Ok(true),
+        _ => Err(invalid_coord_type(flag)),
     }
 }
 
-fn coord_size(flag: u8) -> usize {
+fn coord_size(flag: u8) -> Result<usize, CasErr> {
     match flag {
-        0x00 => 2, // XY without SRID
-        0x20 => 2, // XY with SRID
-        0x40 => 3, // XYM without SRID
-        0x60 => 3, // XYM with SRID
-        0x80 => 3, // XYZ without SRID
-        0xA0 => 3, // XYZ with SRID
-        0xC0 => 4, // XYZM without SRID
-        0xE0 => 4, // XYZM with SRID
-        _ => unreachable!(),
+        0x00 => Ok(2), // XY without SRID
+        0x20 => Ok(2), // XY with SRID
+        0x40 => Ok(3), // XYM without SRID
+        0x60 => Ok(3), // XYM with SRID
+        0x80 => Ok(3), // XYZ without SRID
+        0xA0 => Ok(3), // XYZ with SRID
+        0xC0 => Ok(4), // XYZM without SRID
+        0xE0 => Ok(4), // XYZM with SRID
+        _ => Err(invalid_coord_type(flag)),
+    }
+}
+
+fn invalid_coord_type(flag: u8) -> CasErr {
+    CasErr::DecodeErr(format!("Invalid EWKB coordinate type: {:#04x}", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bboxes() {
+        assert_eq!(
+            decode_box2d(b"BOX(1.5 2.5,3.5 4.5)").unwrap(),
+            Value::Array(vec![
+                Value::Float(1.5),
+                Value::Float(2.5),
+                Value::Float(3.5),
+                Value::Float(4.5)
+            ])
+        );
+        assert_eq!(
+            decode_box3d(b"BOX3D(1 2 3,4 5 -6)").unwrap(),
+            Value::Array(vec![
+                Value::Float(1.0),
+                Value::Float(2.0),
+                Value::Float(3.0),
+                Value::Float(4.0),
+                Value::Float(5.0),
+                Value::Float(-6.0)
+            ])
+        );
+        assert!(decode_box2d(b"BOX3D(1 2 3,4 5 6)").is_err());
+        assert!(decode_box3d(b"BOX3D(1 2,3 4)").is_err());
+    }
+
+    #[test]
+    fn test_geography_default_srid() {
+        // POINT(1.5 3.5), little endian and without an SRID.
+        let mut bytes = vec![1, 1, 0, 0, 0];
+        bytes.extend_from_slice(&1.5f64.to_le_bytes());
+        bytes.extend_from_slice(&3.5f64.to_le_bytes());
+        assert_eq!(
+            decode_geography(&bytes).unwrap(),
+            Value::Geometry(
+                r#"{"type":"Point","crs":{"type":"name","properties":{"name":"EPSG:4326"}},"coordinates":[1.5,3.5]}"#
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            decode_geom(&bytes).unwrap(),
+            Value::Geometry(r#"{"type":"Point","coordinates":[1.5,3.5]}"#.to_string())
+        );
+    }
+
+    // Builds EWKB in either byte order, for geometries that can’t be queried without Postgis.
+    struct Ewkb {
+        bytes: Vec<u8>,
+        little_endian: bool,
+    }
+
+    impl Ewkb {
+        fn new(little_endian: bool) -> Self {
+            Ewkb {
+                bytes: Vec::new(),
+                little_endian,
+            }
+        }
+
+        fn header(mut self, little_endian: bool, geom_type: u32, srid: Option<i32>) -> Self {
+            self.little_endian = little_endian;
+            self.bytes.push(little_endian as u8);
+            let flags = if srid.is_some() { 0x2000_0000 } else { 0 };
+            self = self.u32(geom_type | flags);
+            match srid {
+                Some(srid) => self.u32(srid as u32),
+                None => self,
+            }
+        }
+
+        fn u32(mut self, n: u32) -> Self {
+            if self.little_endian {
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            } else {
+                self.bytes.extend_from_slice(&n.to_be_bytes());
+            }
+            self
+        }
+
+        fn f64s(mut self, coords: &[f64]) -> Self {
+            for coord in coords {
+                if self.little_endian {
+                    self.bytes.extend_from_slice(&coord.to_le_bytes());
+                } else {
+                    self.bytes.extend_from_slice(&coord.to_be_bytes());
+                }
+            }
+            self
+        }
+    }
+
+    fn geojson(bytes: &[u8]) -> String {
+        match decode_geom(bytes).unwrap() {
+            Value::Geometry(geojson) => geojson,
+            value => panic!("{:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_geometry_collections() {
+        // SRID=32632;GEOMETRYCOLLECTION(POINT(40 10),GEOMETRYCOLLECTION(LINESTRING(10 10,20 20)),
+        // POINT EMPTY), with the nested collection in big endian.
+        let bytes = Ewkb::new(true)
+            .header(true, 7, Some(32632))
+            .u32(3)
+            .header(true, 1, None)
+            .f64s(&[40.0, 10.0])
+            .header(false, 7, None)
+            .u32(1)
+            .header(false, 2, None)
+            .u32(2)
+            .f64s(&[10.0, 10.0, 20.0, 20.0])
+            .header(true, 1, None)
+            .f64s(&[f64::NAN, f64::NAN])
+            .bytes;
+        assert_eq!(
+            geojson(&bytes),
+            format!(
+                "{}{}{}{}{}",
+                r#"{"type":"GeometryCollection","crs":{"type":"name","properties":{"name":"EPSG:32632"}},"geometries":["#,
+                r#"{"type":"Point","coordinates":[40,10]},"#,
+                r#"{"type":"GeometryCollection","geometries":[{"type":"LineString","coordinates":[[10,10],[20,20]]}]},"#,
+                r#"{"type":"Point","coordinates":[]}"#,
+                r#"]}"#
+            )
+        );
+
+        let empty = Ewkb::new(false).header(false, 7, None).u32(0).bytes;
+        assert_eq!(
+            geojson(&empty),
+            r#"{"type":"GeometryCollection","geometries":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_multi_geometries() {
+        // MULTIPOINT(1 2,3 4), with the points in different byte orders.
+        let bytes = Ewkb::new(true)
+            .header(true, 4, None)
+            .u32(2)
+            .header(false, 1, None)
+            .f64s(&[1.0, 2.0])
+            .header(true, 1, None)
+            .f64s(&[3.0, 4.0])
+            .bytes;
+        assert_eq!(
+            geojson(&bytes),
+            r#"{"type":"MultiPoint","coordinates":[[1,2],[3,4]]}"#
+        );
+
+        let empty = Ewkb::new(true).header(true, 6, None).u32(0).bytes;
+        assert_eq!(
+            geojson(&empty),
+            r#"{"type":"MultiPolygon","coordinates":[]}"#
+        );
+
+        let point = Ewkb::new(true)
+            .header(true, 1, None)
+            .f64s(&[f64::NAN, f64::NAN]);
+        assert_eq!(
+            geojson(&point.bytes),
+            r#"{"type":"Point","coordinates":[]}"#
+        );
+
+        let mixed = Ewkb::new(true)
+            .header(true, 5, None)
+            .u32(1)
+            .header(true, 1, None)
+            .f64s(&[1.0, 2.0])
+            .bytes;
+        assert!(decode_geom(&mixed).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_geometries() {
+        // CIRCULARSTRING(0 0,1 1,2 0)
+        let curve = Ewkb::new(true)
+            .header(true, 8, None)
+            .u32(3)
+            .f64s(&[0.0, 0.0, 1.0, 1.0, 2.0, 0.0])
+            .bytes;
+        assert!(decode_geom(&curve).is_err());
+
+        let invalid_flags = Ewkb::new(true)
+            .header(true, 0x1000_0001, None)
+            .f64s(&[1.0, 2.0])
+            .bytes;
+        assert!(decode_geom(&invalid_flags).is_err());
+        assert!(decode_geom(&[2, 1, 0, 0, 0]).is_err());
     }
 }
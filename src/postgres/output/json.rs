@@ -1,4 +1,5 @@
 use crate::cas_err::CasErr;
+use crate::postgres::output::value::Value;
 use std::io::Write;
 
 const NULL: &[u8] = "null".as_bytes();
@@ -9,9 +10,16 @@ const FALSE: &[u8] = "false".as_bytes();
 
 /// Given:
 /// u8[]: bytes representing UTF-8 characters of a JSON string
-/// Writes:
-/// a JSON string, with the white space removed
-pub fn serialise_json<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
+/// Returns:
+/// a Json, with the white space removed
+pub fn decode_json(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut out = Vec::with_capacity(bytes.len());
+    compact_json(bytes, &mut out)?;
+    let json = String::from_utf8(out).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Json(json))
+}
+
+fn compact_json<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
 where
     Out: Write,
 {
@@ -1,10 +1,17 @@
 mod binary;
+pub mod de;
 mod ekwb;
 mod json;
 mod net;
 mod nums;
+mod range;
+mod record;
+pub mod registry;
 mod ser;
 mod shapes;
+pub mod sink;
 mod text;
+pub mod text_search;
 mod time;
+pub mod value;
 pub mod write;
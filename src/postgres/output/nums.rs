@@ -1,158 +1,171 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
+use crate::postgres::output::registry::Registry;
+use crate::postgres::output::value::Value;
 use std::io::Write;
 
-const LEFT_SQUARE: &[u8] = "[".as_bytes();
-const RIGHT_SQUARE: &[u8] = "]".as_bytes();
-const COMMA: &[u8] = ",".as_bytes();
-const NULL: &[u8] = "null".as_bytes();
 const ZERO: &[u8] = "0".as_bytes();
 const MINUS: &[u8] = "-".as_bytes();
 const DECIMAL: &[u8] = ".".as_bytes();
-const NAN: &[u8] = "\"NaN\"".as_bytes();
-const INFINITY: &[u8] = "\"Infinity\"".as_bytes();
-const NEGATIVE_INFINITY: &[u8] = "\"-Infinity\"".as_bytes();
 
 /// Given:
 /// u8: 0/1, false/true
-/// Writes:
-/// true/false
-pub fn serialise_bool<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
-    if bytes[0] == 1 {
-        out.write("true".as_bytes())?;
-    } else {
-        out.write("false".as_bytes())?;
-    }
-    Ok(())
+/// Returns:
+/// a Bool
+pub fn decode_bool(bytes: &[u8]) -> Result<Value, CasErr> {
+    Ok(Value::Bool(bytes[0] == 1))
 }
 
 /// Given:
 /// i16: value
-/// Writes:
-/// a number
-pub fn serialise_i16<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// an Int
+pub fn decode_i16(bytes: &[u8]) -> Result<Value, CasErr> {
     let int = i16::from_be_bytes([bytes[0], bytes[1]]);
-    itoap::write(out, int)?;
-    Ok(())
+    Ok(Value::Int(int as i64))
 }
 
 /// Given:
 /// i32: value
-/// Writes:
-/// a number
-pub fn serialise_i32<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// an Int
+pub fn decode_i32(bytes: &[u8]) -> Result<Value, CasErr> {
     let int = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-    itoap::write(out, int)?;
-    Ok(())
+    Ok(Value::Int(int as i64))
 }
 
 /// Given:
 /// i64: value
-/// Writes:
-/// a number
-pub fn serialise_i64<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// an Int
+pub fn decode_i64(bytes: &[u8]) -> Result<Value, CasErr> {
     let int = i64::from_be_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
     ]);
-    itoap::write(out, int)?;
-    Ok(())
+    Ok(Value::Int(int))
+}
+
+/// Given:
+/// u64: value, for xid8
+/// Returns:
+/// a Text of the number, since it may not fit in an i64, or be exact as a JSON number
+pub fn decode_u64(bytes: &[u8]) -> Result<Value, CasErr> {
+    let int = u64::from_be_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+    Ok(Value::Text(int.to_string()))
+}
+
+/// Given:
+/// u32: value, for unsigned types like oid and xid
+/// Returns:
+/// an Int
+pub fn decode_u32(bytes: &[u8]) -> Result<Value, CasErr> {
+    let int = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok(Value::Int(int as i64))
+}
+
+/// Given:
+/// u32: the oid of a row in the catalog the reg* type refers to, e.g. pg_class for regclass
+/// Returns:
+/// a Text of the row’s name, if it was looked up, or else an Int of the oid, as when it refers to
+/// a row that doesn’t exist
+pub fn decode_reg_name(bytes: &[u8], reg_type: i32, registry: &Registry) -> Result<Value, CasErr> {
+    let oid = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    match registry.reg_name(reg_type, oid) {
+        Some(name) => Ok(Value::Text(name.to_owned())),
+        None => Ok(Value::Int(oid as i64)),
+    }
+}
+
+/// Given:
+/// i64: the amount in the currency’s smallest unit, e.g. cents
+/// Returns:
+/// a Numeric, with the number of fraction digits of the server’s lc_monetary
+pub fn decode_money(bytes: &[u8], fraction_digits: u32) -> Result<Value, CasErr> {
+    let amount = i64::from_be_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+    let sign = if amount < 0 { "-" } else { "" };
+    let digits = format!(
+        "{:0>width$}",
+        amount.unsigned_abs(),
+        width = fraction_digits as usize + 1
+    );
+    let (whole, fraction) = digits.split_at(digits.len() - fraction_digits as usize);
+    if fraction.is_empty() {
+        Ok(Value::Numeric(format!("{}{}", sign, whole)))
+    } else {
+        Ok(Value::Numeric(format!("{}{}.{}", sign, whole, fraction)))
+    }
+}
+
+/// Given:
+/// u64: a write-ahead log location
+/// Returns:
+/// a Text of the upper and lower 32 bits in hex, as Postgres writes it, e.g. 16/B374D848
+pub fn decode_lsn(bytes: &[u8]) -> Result<Value, CasErr> {
+    let lsn = u64::from_be_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+    Ok(Value::Text(format!(
+        "{:X}/{:X}",
+        lsn >> 32,
+        lsn & 0xffff_ffff
+    )))
+}
+
+/// Given:
+/// i32: number of transactions in progress
+/// u64: xmin, the earliest transaction still in progress
+/// u64: xmax, the first transaction that hasn’t started yet
+/// u64[]: the transactions in progress
+/// Returns:
+/// a Record, written as {"xmin":"..","xmax":"..","xip":[".."]}, with the transaction ids as
+/// strings, like xid8s
+pub fn decode_snapshot(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut snapshot = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let xip_count = snapshot.i32();
+    let mut xid = || Value::Text((snapshot.i64() as u64).to_string());
+    let xmin = xid();
+    let xmax = xid();
+    let xip = (0..xip_count).map(|_| xid()).collect();
+    Ok(Value::Record(vec![
+        ("xmin".to_string(), xmin),
+        ("xmax".to_string(), xmax),
+        ("xip".to_string(), Value::Array(xip)),
+    ]))
 }
 
 /// Given:
 /// f32: value
-/// Writes:
-/// a number, or "NaN" or "Infinity" or "-Infinity"
-///
-/// JSON doesn't include a way to indicate NaN, Infinity or -Infinity because JSON numbers
-/// aren't tied to any particular implementation of number, floating point or otherwise.
-///
-/// serde_json serialises them to null, which makes sense, but for casql I would prefer to
-/// retain more information, so I have decided to write them as strings for now. Different
-/// languages will be able to parse different strings as floats, so this may cause issues.
-pub fn serialise_f32<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Float32, which is kept separate from Float so that it is written with f32 precision
+pub fn decode_f32(bytes: &[u8]) -> Result<Value, CasErr> {
     let float = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-    if float.is_finite() {
-        let mut ryu_buf = ryu::Buffer::new();
-        let str = ryu_buf.format(float);
-        out.write(str.as_bytes())?;
-    } else if float.is_nan() {
-        out.write(NAN)?;
-    } else if float.is_infinite() {
-        if float.is_sign_negative() {
-            out.write(NEGATIVE_INFINITY)?;
-        } else {
-            out.write(INFINITY)?;
-        }
-    }
-    Ok(())
+    Ok(Value::Float32(float))
 }
 
 /// Given:
 /// f64: value
-/// Writes:
-/// a number, or "NaN" or "Infinity" or "-Infinity"
-///
-/// JSON doesn't include a way to indicate NaN, Infinity or -Infinity because JSON numbers
-/// aren't tied to any particular implementation of number, floating point or otherwise.
-///
-/// serde_json serialises them to null, which makes sense, but for casql I would prefer to
-/// retain more information, so I have decided to write them as strings for now. Different
-/// languages will be able to parse different strings as floats, so this may cause issues.
-pub fn serialise_f64<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Float
+pub fn decode_f64(bytes: &[u8]) -> Result<Value, CasErr> {
     let float = f64::from_be_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
     ]);
-    if float.is_finite() {
-        let mut ryu_buf = ryu::Buffer::new();
-        let str = ryu_buf.format(float);
-        out.write(str.as_bytes())?;
-    } else if float.is_nan() {
-        out.write(NAN)?;
-    } else if float.is_infinite() {
-        if float.is_sign_negative() {
-            out.write(NEGATIVE_INFINITY)?;
-        } else {
-            out.write(INFINITY)?;
-        }
-    }
-    Ok(())
+    Ok(Value::Float(float))
 }
 
 /// Given:
 /// i32: block
 /// i16: offset
 ///
-/// Writes a two-element array representing the (block, offset) tuple.
-pub fn serialise_tid<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns a Tid representing the (block, offset) tuple.
+pub fn decode_tid(bytes: &[u8]) -> Result<Value, CasErr> {
     let block = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
     let offset = i16::from_be_bytes([bytes[4], bytes[5]]);
-    out.write(LEFT_SQUARE)?;
-    itoap::write(&mut *out, block)?;
-    out.write(COMMA)?;
-    itoap::write(&mut *out, offset)?;
-    out.write(RIGHT_SQUARE)?;
-    Ok(())
+    Ok(Value::Tid(block, offset))
 }
 
 /// Given:
@@ -161,12 +174,10 @@ where
 /// i16: flag, magic numbers indicating negative or NaN or NULL
 /// i16: scale, number of specified decimals places
 /// i16[]: digit-blocks, a number representing 4 digits
-/// Writes:
-/// a number
-pub fn serialise_bignum<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Numeric, with the number as a decimal string, or "NaN"
+pub fn decode_bignum(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut out = Vec::new();
     let mut bignum = BinaryReader::from(bytes, ByteOrder::BigEndian);
     let mut digits = bignum.i16();
     let mut weight = bignum.i16();
@@ -181,27 +192,25 @@ where
         }
         -16384 => {
             // 0xC000
-            out.write(NAN)?;
-            return Ok(());
+            return Ok(Value::Numeric("NaN".to_owned()));
         }
         -4096 => {
             // 0xF000
-            out.write(NULL)?;
-            return Ok(());
+            return Ok(Value::Null);
         }
         _ => {}
     }
     // If it’s zero, just write zero.
     if digits == 0 {
         out.write(ZERO)?;
-        return Ok(());
+        return numeric(out);
     }
 
     // Write integral part.
     if weight >= 0 {
         // Write the first block without leading zeros.
         let first_block = bignum.i16();
-        itoap::write(&mut (*out), first_block)?;
+        itoap::write(&mut out, first_block)?;
         digits -= 1;
         weight -= 1;
         // Write subsequent integral blocks as zero-padded.
@@ -218,7 +227,7 @@ where
     if scale > 0 {
         out.write(DECIMAL)?;
     } else {
-        return Ok(());
+        return numeric(out);
     }
     // Add leading zeros if necessary, i.e. if the first digit block is more than 4 zeros
     // after the decimal. If we’ve just written an integral part, the weight will be -1.
@@ -241,5 +250,27 @@ where
         let trimmed = &digits.as_bytes()[0..(scale as usize)];
         out.write(trimmed)?;
     }
-    Ok(())
+    numeric(out)
+}
+
+fn numeric(digits: Vec<u8>) -> Result<Value, CasErr> {
+    let digits = String::from_utf8(digits).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Numeric(digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_money_fraction_digits() {
+        let cents = (-1234i64).to_be_bytes();
+        let money = |digits| match decode_money(&cents, digits).unwrap() {
+            Value::Numeric(num) => num,
+            value => panic!("{:?}", value),
+        };
+        assert_eq!(money(2), "-12.34");
+        assert_eq!(money(0), "-1234");
+        assert_eq!(money(5), "-0.01234");
+    }
 }
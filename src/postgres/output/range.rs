@@ -0,0 +1,101 @@
+use crate::binary_reader::{BinaryReader, ByteOrder};
+use crate::cas_err::CasErr;
+use crate::postgres::output::registry::{Registry, Serialiser};
+use crate::postgres::output::value::Value;
+
+// https://github.com/postgres/postgres/blob/master/src/include/utils/rangetypes.h
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LB_INC: u8 = 0x02;
+const RANGE_UB_INC: u8 = 0x04;
+const RANGE_LB_INF: u8 = 0x08;
+const RANGE_UB_INF: u8 = 0x10;
+
+/// Given:
+/// u8: flags, for whether it is empty, and whether each bound is inclusive or infinite
+/// [i32, u8[]]: the size and bytes of the lower bound, unless it is infinite
+/// [i32, u8[]]: the size and bytes of the upper bound, unless it is infinite
+///
+/// Returns:
+/// a Range, with Nulls for infinite bounds, or an EmptyRange
+pub fn decode_range(bytes: &[u8], subtype: i32, registry: &Registry) -> Result<Value, CasErr> {
+    let mut range = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let serialiser = registry.find(subtype);
+    read_range(&mut range, serialiser.as_ref(), registry)
+}
+
+/// Given:
+/// i32: number of ranges
+/// [i32, u8[]]: the size and bytes of each range
+///
+/// Returns:
+/// an Array of the ranges
+pub fn decode_multirange(bytes: &[u8], subtype: i32, registry: &Registry) -> Result<Value, CasErr> {
+    let mut multirange = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let serialiser = registry.find(subtype);
+    let range_count = multirange.i32();
+    let mut ranges = Vec::with_capacity(range_count as usize);
+    for _ in 0..range_count {
+        let size = multirange.i32();
+        let mut range =
+            BinaryReader::from(multirange.byte_slice(size as usize), ByteOrder::BigEndian);
+        ranges.push(read_range(&mut range, serialiser.as_ref(), registry)?);
+    }
+    Ok(Value::Array(ranges))
+}
+
+/// Calls f with the bytes of each bound of the range that isn’t infinite.
+pub fn visit_bounds(bytes: &[u8], f: &mut dyn FnMut(&[u8])) {
+    let mut range = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let flags = range.u8();
+    if flags & RANGE_EMPTY != 0 {
+        return;
+    }
+    for infinite in [flags & RANGE_LB_INF != 0, flags & RANGE_UB_INF != 0] {
+        if !infinite {
+            let size = range.i32();
+            f(range.byte_slice(size as usize));
+        }
+    }
+}
+
+/// Calls f with the bytes of each bound of each range of the multirange.
+pub fn visit_multirange_bounds(bytes: &[u8], f: &mut dyn FnMut(&[u8])) {
+    let mut multirange = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let range_count = multirange.i32();
+    for _ in 0..range_count {
+        let size = multirange.i32();
+        visit_bounds(multirange.byte_slice(size as usize), f);
+    }
+}
+
+fn read_range(
+    range: &mut BinaryReader,
+    serialiser: &dyn Serialiser,
+    registry: &Registry,
+) -> Result<Value, CasErr> {
+    let flags = range.u8();
+    if flags & RANGE_EMPTY != 0 {
+        return Ok(Value::EmptyRange);
+    }
+    let lower = read_bound(range, flags & RANGE_LB_INF != 0, serialiser, registry)?;
+    let upper = read_bound(range, flags & RANGE_UB_INF != 0, serialiser, registry)?;
+    Ok(Value::Range {
+        lower: Box::new(lower),
+        upper: Box::new(upper),
+        lower_inc: flags & RANGE_LB_INC != 0,
+        upper_inc: flags & RANGE_UB_INC != 0,
+    })
+}
+
+fn read_bound(
+    range: &mut BinaryReader,
+    infinite: bool,
+    serialiser: &dyn Serialiser,
+    registry: &Registry,
+) -> Result<Value, CasErr> {
+    if infinite {
+        return Ok(Value::Null);
+    }
+    let size = range.i32();
+    serialiser.decode(range.byte_slice(size as usize), registry)
+}
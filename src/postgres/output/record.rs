@@ -0,0 +1,85 @@
+use crate::binary_reader::{BinaryReader, ByteOrder};
+use crate::cas_err::CasErr;
+use crate::postgres::backend_msgs::Attribute;
+use crate::postgres::output::registry::{Registry, Serialiser};
+use crate::postgres::output::value::Value;
+
+pub(crate) const RECORD_OID: i32 = 2249;
+pub(crate) const RECORD_ARRAY_OID: i32 = 2287;
+
+/// Given:
+/// i32: number of fields
+/// [i32, i32, u8[]]: the oid, size and bytes of each field, -1 for null
+///
+/// Returns:
+/// an Array of the fields, since an anonymous record has no field names
+pub fn decode_record(bytes: &[u8], registry: &Registry) -> Result<Value, CasErr> {
+    Ok(Value::Array(decode_fields(bytes, registry)?))
+}
+
+/// Given:
+/// the bytes of a record, or of a composite, which is sent in the same format
+///
+/// Calls f with the oid and bytes of each field that isn’t null. The field types of an anonymous
+/// record are only known from its values, so they are looked up after the rows are read.
+pub fn visit_fields(bytes: &[u8], f: &mut dyn FnMut(i32, &[u8])) {
+    let mut record = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let field_count = record.i32();
+    for _ in 0..field_count {
+        let oid = record.i32();
+        let size = record.i32();
+        if size != -1 {
+            f(oid, record.byte_slice(size as usize));
+        }
+    }
+}
+
+fn decode_fields(bytes: &[u8], registry: &Registry) -> Result<Vec<Value>, CasErr> {
+    let mut record = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let field_count = record.i32();
+    let mut fields = Vec::with_capacity(field_count as usize);
+    for _ in 0..field_count {
+        let oid = record.i32();
+        let size = record.i32();
+        if size == -1 {
+            fields.push(Value::Null);
+        } else {
+            let serialiser = registry.find_looked_up(oid)?;
+            fields.push(serialiser.decode(record.byte_slice(size as usize), registry)?);
+        }
+    }
+    Ok(fields)
+}
+
+/// Decodes a composite type, which is sent in the same format as a record, as a Record with the
+/// names of its attributes.
+pub struct CompositeSerialiser {
+    names: Vec<String>,
+}
+
+impl CompositeSerialiser {
+    pub fn new(attributes: &[Attribute]) -> Self {
+        CompositeSerialiser {
+            names: attributes
+                .iter()
+                .map(|attribute| attribute.name.clone())
+                .collect(),
+        }
+    }
+}
+
+impl Serialiser for CompositeSerialiser {
+    fn decode(&self, bytes: &[u8], registry: &Registry) -> Result<Value, CasErr> {
+        let fields = decode_fields(bytes, registry)?;
+        if fields.len() != self.names.len() {
+            return Err(CasErr::DecodeErr(format!(
+                "Composite value has {} fields, but its type has {} attributes.",
+                fields.len(),
+                self.names.len()
+            )));
+        }
+        Ok(Value::Record(
+            self.names.iter().cloned().zip(fields).collect(),
+        ))
+    }
+}
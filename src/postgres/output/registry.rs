@@ -0,0 +1,632 @@
+use crate::cas_err::CasErr;
+use crate::postgres::backend_msgs::{Field, PgType};
+use crate::postgres::output::range;
+use crate::postgres::output::record::{self, CompositeSerialiser, RECORD_ARRAY_OID, RECORD_OID};
+use crate::postgres::output::ser::{find_serialiser, Ser};
+use crate::postgres::output::text_search::TextSearchFormat;
+use crate::postgres::output::value::{self, Value};
+use crate::postgres::params::{BINARY_FORMAT, TEXT_FORMAT};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// regproc, regprocedure, regoper, regoperator, regclass, regtype, regconfig, regdictionary,
+// regnamespace, regrole and regcollation.
+const REG_TYPE_OIDS: [i32; 11] = [
+    24, 2202, 2203, 2204, 2205, 2206, 3734, 3769, 4089, 4096, 4191,
+];
+// Their arrays.
+const REG_ARRAY_OIDS: [i32; 11] = [
+    1008, 2207, 2208, 2209, 2210, 2211, 3735, 3770, 4090, 4097, 4192,
+];
+// Types that have no binary format, so they are requested as text, and decoded from that.
+const TEXT_ONLY_NAMES: [&str; 2] = ["box2d", "box3d"];
+const MONEY_OID: i32 = 790;
+const MONEY_ARRAY_OID: i32 = 791;
+
+/// Decodes the binary representation of a Postgres type. The registry is passed in so that
+/// serialisers for container types, like arrays, can find the serialisers for their elements.
+///
+/// It is implemented for closures, so a built-in serialiser can be wrapped to change its output:
+/// ```ignore
+/// let numeric = registry.find(1700);
+/// registry.register_oid(1700, move |bytes: &[u8], registry: &Registry| {
+///     match numeric.decode(bytes, registry)? {
+///         Value::Numeric(num) => Ok(Value::Text(num)),
+///         value => Ok(value),
+///     }
+/// });
+/// ```
+pub trait Serialiser: Send + Sync {
+    fn decode(&self, bytes: &[u8], registry: &Registry) -> Result<Value, CasErr>;
+}
+
+impl<F> Serialiser for F
+where
+    F: Fn(&[u8], &Registry) -> Result<Value, CasErr> + Send + Sync,
+{
+    fn decode(&self, bytes: &[u8], registry: &Registry) -> Result<Value, CasErr> {
+        self(bytes, registry)
+    }
+}
+
+impl Serialiser for Ser {
+    fn decode(&self, bytes: &[u8], registry: &Registry) -> Result<Value, CasErr> {
+        value::decode(bytes, self, registry)
+    }
+}
+
+/// How the reg* types, which are aliases for the oids of rows in the catalogs, like regclass and
+/// regtype, are decoded: as the oid, or as the name of the table, type, etc. it refers to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RegTypeFormat {
+    #[default]
+    Oid,
+    Name,
+}
+
+impl FromStr for RegTypeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oid" => Ok(RegTypeFormat::Oid),
+            "name" => Ok(RegTypeFormat::Name),
+            _ => Err(format!(
+                "Invalid reg type format: {}. Expected oid or name.",
+                s
+            )),
+        }
+    }
+}
+
+/// The serialisers for each type, found by oid, or by name for types whose oids aren’t fixed, such
+/// as those from extensions. Types that aren’t registered use the built-in serialisers.
+///
+/// The oids of the registered names are looked up when the registry is given to a connection, and
+/// other types without a serialiser are looked up in pg_type the first time they are queried. If
+/// they turn out to be enums, domains or arrays, they are decoded like their labels, base types
+/// or elements. Domains can be based on other domains, so their base types are looked up until
+/// one is found that can be decoded.
+#[derive(Clone)]
+pub struct Registry {
+    by_oid: HashMap<i32, Arc<dyn Serialiser>>,
+    by_name: HashMap<String, Arc<dyn Serialiser>>,
+    // The types that have been looked up in pg_type.
+    dynamic_types: HashMap<i32, PgType>,
+    // The oids of the types that were loaded from an earlier connection, rather than looked up.
+    cached_oids: HashSet<i32>,
+    // The number of fraction digits of money values, which depends on the server’s lc_monetary,
+    // once it has been looked up.
+    money_fraction_digits: Option<u32>,
+    reg_type_format: RegTypeFormat,
+    // The names the reg* values in the current query’s rows refer to, by reg type and oid.
+    reg_names: HashMap<(i32, u32), String>,
+    // The text the server’s output functions give for the values of types without a serialiser,
+    // within the current query’s records, by type and binary value.
+    output_texts: HashMap<i32, HashMap<Vec<u8>, String>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry {
+            by_oid: HashMap::new(),
+            by_name: HashMap::new(),
+            dynamic_types: HashMap::new(),
+            cached_oids: HashSet::new(),
+            money_fraction_digits: None,
+            reg_type_format: RegTypeFormat::Oid,
+            reg_names: HashMap::new(),
+            output_texts: HashMap::new(),
+        };
+        registry.register_name("geometry", Ser::EWKB);
+        registry.register_name("geography", Ser::Geography);
+        registry.register_name("box2d", Ser::Box2d);
+        registry.register_name("box3d", Ser::Box3d);
+        registry.register_name("hstore", Ser::Hstore);
+        registry
+    }
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("oids", &self.by_oid.keys().collect::<Vec<_>>())
+            .field("names", &self.by_name.keys().collect::<Vec<_>>())
+            .field(
+                "dynamic_types",
+                &self.dynamic_types.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Registry {
+    /// A registry with only the built-in serialisers.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Registers a serialiser for the type with the oid, replacing the built-in one, if any.
+    pub fn register_oid<S>(&mut self, oid: i32, serialiser: S)
+    where
+        S: Serialiser + 'static,
+    {
+        self.by_oid.insert(oid, Arc::new(serialiser));
+    }
+
+    /// Registers a serialiser for the type with the name, e.g. an extension type like hstore.
+    pub fn register_name<S>(&mut self, name: &str, serialiser: S)
+    where
+        S: Serialiser + 'static,
+    {
+        self.by_name.insert(name.to_owned(), Arc::new(serialiser));
+    }
+
+    /// Decodes tsvector and tsquery values in the format, instead of as the text Postgres writes
+    /// for them.
+    pub fn set_text_search_format(&mut self, format: TextSearchFormat) {
+        self.register_oid(3614, Ser::TsVector(format));
+        self.register_oid(3615, Ser::TsQuery(format));
+    }
+
+    /// Decodes the reg* types, like regclass, in the format, instead of as oids. The names are
+    /// looked up in the catalogs once a query’s rows have been read, for the values in them,
+    /// including those within arrays, composites, records and ranges. They are always qualified
+    /// with their schema, e.g. public.users, except for regnamespace and regrole.
+    pub fn set_reg_type_format(&mut self, format: RegTypeFormat) {
+        self.reg_type_format = format;
+    }
+
+    /// Finds the serialiser for the oid: one registered for it, or for its name, or else the
+    /// built-in one. Types without any are decoded as Value::Unknown.
+    pub fn find(&self, oid: i32) -> Arc<dyn Serialiser> {
+        self.resolve(oid).unwrap_or_else(|| Arc::new(Ser::Unknown))
+    }
+
+    /// Finds the serialiser for a type that is only known from a value, like a field of an
+    /// anonymous record. Unless it is built in, it has to have been looked up, and if there is no
+    /// serialiser for it, it is decoded as the text the server converted it to.
+    pub(crate) fn find_looked_up(&self, oid: i32) -> Result<Arc<dyn Serialiser>, CasErr> {
+        match self.resolve(oid) {
+            Some(serialiser) => Ok(serialiser),
+            None if self.dynamic_types.contains_key(&oid) => Ok(Arc::new(Ser::OutputText(oid))),
+            None => Err(CasErr::DecodeErr(format!(
+                "The type of a record field, with oid {}, hasn’t been looked up.",
+                oid
+            ))),
+        }
+    }
+
+    /// Whether the type can be decoded from its binary format, by a registered or built-in
+    /// serialiser. Columns of other types are requested in the server’s text format instead.
+    pub fn decodes_binary(&self, oid: i32) -> bool {
+        self.resolve(oid).is_some()
+    }
+
+    fn resolve(&self, oid: i32) -> Option<Arc<dyn Serialiser>> {
+        if let Some(serialiser) = self.by_oid.get(&oid) {
+            return Some(serialiser.clone());
+        }
+        if self.names_reg_type(oid) {
+            return Some(Arc::new(Ser::RegName(oid)));
+        }
+        let dynamic_type = self.dynamic_types.get(&oid);
+        if let Some(serialiser) = dynamic_type.and_then(|pg_type| self.by_name.get(&pg_type.name)) {
+            return Some(serialiser.clone());
+        }
+        match (find_serialiser(oid), dynamic_type) {
+            (Ser::Unknown, Some(pg_type)) => self.resolve_dynamic(pg_type),
+            (Ser::Unknown, None) => None,
+            (ser, _) => Some(Arc::new(ser)),
+        }
+    }
+
+    /// Chooses a serialiser for a type that isn’t built in, from its row in pg_type.
+    fn resolve_dynamic(&self, pg_type: &PgType) -> Option<Arc<dyn Serialiser>> {
+        match pg_type.typtype {
+            // An enum is sent as its label.
+            b'e' => Some(Arc::new(Ser::Enum)),
+            b'd' => self.resolve(pg_type.base_type),
+            // Array types are named after their element type, with a leading underscore.
+            b'b' if pg_type.elem != 0 && pg_type.name.starts_with('_') => {
+                self.resolve(pg_type.elem)?;
+                Some(Arc::new(Ser::Array))
+            }
+            b'r' => {
+                self.resolve(pg_type.range_subtype)?;
+                Some(Arc::new(Ser::Range(pg_type.range_subtype)))
+            }
+            b'm' => {
+                self.resolve(pg_type.range_subtype)?;
+                Some(Arc::new(Ser::Multirange(pg_type.range_subtype)))
+            }
+            b'c' if pg_type
+                .attributes
+                .iter()
+                .all(|attribute| self.resolve(attribute.type_oid).is_some()) =>
+            {
+                Some(Arc::new(CompositeSerialiser::new(&pg_type.attributes)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The format to request for each column of a result: binary if its type can be decoded, and
+    /// text otherwise.
+    pub(crate) fn result_formats(&self, fields: &[Field]) -> Vec<i16> {
+        fields
+            .iter()
+            .map(|field| {
+                if self.decodes_binary(field.data_type_oid) && !self.text_only(field) {
+                    BINARY_FORMAT
+                } else {
+                    TEXT_FORMAT
+                }
+            })
+            .collect()
+    }
+
+    // Arrays of the text only types are requested as text too, but are kept as the server’s text.
+    fn text_only(&self, field: &Field) -> bool {
+        self.dynamic_types
+            .get(&field.data_type_oid)
+            .is_some_and(|pg_type| {
+                is_text_only(pg_type)
+                    || self
+                        .dynamic_types
+                        .get(&pg_type.elem)
+                        .is_some_and(is_text_only)
+            })
+    }
+
+    /// Finds the serialiser for a column, which for values sent as text just keeps the text,
+    /// unless the type only has a text format.
+    pub(crate) fn find_for_field(&self, field: &Field) -> Arc<dyn Serialiser> {
+        let text_only_type = self
+            .dynamic_types
+            .get(&field.data_type_oid)
+            .is_some_and(is_text_only);
+        if field.format == TEXT_FORMAT && text_only_type {
+            self.find(field.data_type_oid)
+        } else if field.format == TEXT_FORMAT {
+            Arc::new(Ser::ServerText)
+        } else {
+            self.find(field.data_type_oid)
+        }
+    }
+
+    /// The row of pg_type for a type that has been looked up, because it has no built-in
+    /// serialiser, or it was registered by name.
+    pub fn dynamic_type(&self, oid: i32) -> Option<&PgType> {
+        self.dynamic_types.get(&oid)
+    }
+
+    /// Decodes a value of the type with the oid.
+    pub fn decode(&self, oid: i32, bytes: &[u8]) -> Result<Value, CasErr> {
+        self.find(oid).decode(bytes, self)
+    }
+
+    /// The registered names, whose oids need to be looked up.
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.by_name.keys().cloned().collect()
+    }
+
+    /// The oids of the fields’ types that need to be looked up in pg_type. If there are any, the
+    /// types loaded from an earlier connection may be out of date, so they are dropped, and
+    /// looked up again if the fields use them.
+    pub(crate) fn field_oids_to_look_up(&mut self, fields: &[Field]) -> Vec<i32> {
+        let oids = fields.iter().map(|field| field.data_type_oid);
+        let unresolved = self.unresolved_oids(oids.clone());
+        if !unresolved.is_empty() && self.invalidate_cached_types() {
+            self.unresolved_oids(oids)
+        } else {
+            unresolved
+        }
+    }
+
+    /// Whether the rows have to be read before they can be decoded, because they may hold
+    /// anonymous records, whose field types are only known from the rows, or reg* values to be
+    /// named. The rows are then read into memory, so that the connection can be used to look them
+    /// up.
+    pub(crate) fn needs_row_pass(&self, fields: &[Field]) -> bool {
+        let needs_pass = |oid: i32| {
+            matches!(oid, RECORD_OID | RECORD_ARRAY_OID)
+                || self.names_reg_type(oid)
+                || (self.reg_type_format == RegTypeFormat::Name && REG_ARRAY_OIDS.contains(&oid))
+        };
+        fields.iter().any(|field| {
+            field.format == BINARY_FORMAT && self.may_contain(field.data_type_oid, &needs_pass)
+        })
+    }
+
+    // Whether values of the type may be, or hold, values for which found is true, going through
+    // the types that have been looked up: domains, arrays, composites and ranges.
+    fn may_contain(&self, oid: i32, found: &dyn Fn(i32) -> bool) -> bool {
+        if found(oid) {
+            return true;
+        }
+        match self.dynamic_types.get(&oid) {
+            Some(pg_type) => match pg_type.typtype {
+                b'c' => pg_type
+                    .attributes
+                    .iter()
+                    .any(|attribute| self.may_contain(attribute.type_oid, found)),
+                b'd' => self.may_contain(pg_type.base_type, found),
+                b'r' | b'm' => self.may_contain(pg_type.range_subtype, found),
+                _ => pg_type.elem != 0 && self.may_contain(pg_type.elem, found),
+            },
+            None => false,
+        }
+    }
+
+    /// Calls f with the type and bytes of each value in the row that was sent in binary, and of
+    /// the values within them: the elements of arrays, the fields of records and composites, and
+    /// the bounds of ranges. Domains are visited as their base types too. The values of types that
+    /// haven’t been looked up aren’t looked into.
+    pub(crate) fn visit_row(&self, fields: &[Field], row: &[u8], f: &mut dyn FnMut(i32, &[u8])) {
+        for (field, bytes) in fields.iter().zip(value::split_row(row)) {
+            if let Some(bytes) = bytes {
+                if field.format == BINARY_FORMAT {
+                    self.visit_value(field.data_type_oid, bytes, f);
+                }
+            }
+        }
+    }
+
+    fn visit_value(&self, oid: i32, bytes: &[u8], f: &mut dyn FnMut(i32, &[u8])) {
+        f(oid, bytes);
+        let dynamic_type = self.dynamic_types.get(&oid);
+        match (find_serialiser(oid), dynamic_type) {
+            (Ser::Record, _) => {
+                record::visit_fields(bytes, &mut |oid, bytes| self.visit_value(oid, bytes, f))
+            }
+            (Ser::Array, _) => value::visit_array_elements(bytes, &mut |oid, bytes| {
+                self.visit_value(oid, bytes, f)
+            }),
+            (Ser::Range(subtype), _) => {
+                range::visit_bounds(bytes, &mut |bytes| self.visit_value(subtype, bytes, f))
+            }
+            (Ser::Multirange(subtype), _) => range::visit_multirange_bounds(bytes, &mut |bytes| {
+                self.visit_value(subtype, bytes, f)
+            }),
+            (Ser::Unknown, Some(pg_type)) => match pg_type.typtype {
+                b'c' => {
+                    record::visit_fields(bytes, &mut |oid, bytes| self.visit_value(oid, bytes, f))
+                }
+                b'd' => self.visit_value(pg_type.base_type, bytes, f),
+                b'r' => range::visit_bounds(bytes, &mut |bytes| {
+                    self.visit_value(pg_type.range_subtype, bytes, f)
+                }),
+                b'm' => range::visit_multirange_bounds(bytes, &mut |bytes| {
+                    self.visit_value(pg_type.range_subtype, bytes, f)
+                }),
+                b'b' if pg_type.elem != 0 && pg_type.name.starts_with('_') => {
+                    value::visit_array_elements(bytes, &mut |oid, bytes| {
+                        self.visit_value(oid, bytes, f)
+                    })
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Whether values of the type are decoded as the names they refer to, which are looked up
+    /// after the rows are read.
+    pub(crate) fn names_reg_type(&self, oid: i32) -> bool {
+        self.reg_type_format == RegTypeFormat::Name && REG_TYPE_OIDS.contains(&oid)
+    }
+
+    /// Replaces the names of the reg* values, with those looked up for the current query, as the
+    /// reg type, oid and name.
+    pub(crate) fn set_reg_names(&mut self, names: Vec<(i32, u32, String)>) {
+        self.reg_names = names
+            .into_iter()
+            .map(|(reg_type, oid, name)| ((reg_type, oid), name))
+            .collect();
+    }
+
+    /// The name the oid refers to, as a value of the reg type, if it has been looked up.
+    pub(crate) fn reg_name(&self, reg_type: i32, oid: u32) -> Option<&str> {
+        self.reg_names
+            .get(&(reg_type, oid))
+            .map(|name| name.as_str())
+    }
+
+    /// Whether values of the type have to be converted to text by the server, because it has been
+    /// looked up for a record field, but there is no serialiser for it.
+    pub(crate) fn needs_output_text(&self, oid: i32) -> bool {
+        self.dynamic_types.contains_key(&oid) && self.resolve(oid).is_none()
+    }
+
+    /// Adds the text the server converted values to, as the type, binary value and text.
+    pub(crate) fn add_output_texts<I>(&mut self, texts: I)
+    where
+        I: IntoIterator<Item = (i32, Vec<u8>, String)>,
+    {
+        for (oid, bytes, text) in texts {
+            self.output_texts
+                .entry(oid)
+                .or_default()
+                .insert(bytes, text);
+        }
+    }
+
+    /// The text for a value of the type, if the server converted it.
+    pub(crate) fn output_text(&self, oid: i32, bytes: &[u8]) -> Option<&str> {
+        self.output_texts
+            .get(&oid)
+            .and_then(|texts| texts.get(bytes))
+            .map(|text| text.as_str())
+    }
+
+    /// Forgets the names and texts that were looked up for the previous query’s rows.
+    pub(crate) fn clear_row_lookups(&mut self) {
+        self.reg_names.clear();
+        self.output_texts.clear();
+    }
+
+    /// The oids that need to be looked up in pg_type, because they don’t have a serialiser and
+    /// haven’t been looked up already.
+    pub(crate) fn unresolved_oids<I>(&self, oids: I) -> Vec<i32>
+    where
+        I: IntoIterator<Item = i32>,
+    {
+        let mut unresolved = Vec::new();
+        for oid in oids {
+            if !self.by_oid.contains_key(&oid)
+                && !self.dynamic_types.contains_key(&oid)
+                && matches!(find_serialiser(oid), Ser::Unknown)
+                && !unresolved.contains(&oid)
+            {
+                unresolved.push(oid);
+            }
+        }
+        unresolved
+    }
+
+    /// Adds the types that have been looked up, and returns the oids of their elements and base
+    /// types that still need to be.
+    pub(crate) fn add_types(&mut self, types: Vec<PgType>) -> Vec<i32> {
+        let referenced: Vec<i32> = types
+            .iter()
+            .flat_map(|pg_type| {
+                let attributes = pg_type
+                    .attributes
+                    .iter()
+                    .map(|attribute| attribute.type_oid);
+                vec![pg_type.elem, pg_type.base_type, pg_type.range_subtype]
+                    .into_iter()
+                    .chain(attributes)
+            })
+            .filter(|oid| *oid != 0)
+            .collect();
+        for pg_type in types {
+            self.dynamic_types.insert(pg_type.oid, pg_type);
+        }
+        self.unresolved_oids(referenced)
+    }
+
+    /// Whether the server has to be asked how many fraction digits money values have, before the
+    /// columns can be decoded.
+    pub(crate) fn needs_money_fraction_digits(&self, fields: &[Field]) -> bool {
+        self.money_fraction_digits.is_none()
+            && fields
+                .iter()
+                .any(|field| matches!(field.data_type_oid, MONEY_OID | MONEY_ARRAY_OID))
+    }
+
+    /// The number of fraction digits of money values, which is 2 until it has been looked up, as
+    /// it is for most currencies.
+    pub(crate) fn money_fraction_digits(&self) -> u32 {
+        self.money_fraction_digits.unwrap_or(2)
+    }
+
+    pub(crate) fn set_money_fraction_digits(&mut self, digits: u32) {
+        self.money_fraction_digits = Some(digits);
+    }
+
+    /// Keeps the types that were looked up for the registry this one replaces.
+    pub(crate) fn inherit_types(&mut self, previous: Registry) {
+        for (oid, pg_type) in previous.dynamic_types {
+            self.dynamic_types.entry(oid).or_insert(pg_type);
+        }
+        self.cached_oids.extend(previous.cached_oids);
+    }
+
+    pub(crate) fn dynamic_types(&self) -> Vec<PgType> {
+        self.dynamic_types.values().cloned().collect()
+    }
+
+    /// Adds types that were looked up by an earlier connection, without replacing any that have
+    /// been looked up by this one.
+    pub(crate) fn load_types(&mut self, types: Vec<PgType>) {
+        for pg_type in types {
+            if !self.dynamic_types.contains_key(&pg_type.oid) {
+                self.cached_oids.insert(pg_type.oid);
+                self.dynamic_types.insert(pg_type.oid, pg_type);
+            }
+        }
+    }
+
+    /// Removes the types loaded from an earlier connection, and returns whether there were any.
+    pub(crate) fn invalidate_cached_types(&mut self) -> bool {
+        let had_cached = !self.cached_oids.is_empty();
+        for oid in self.cached_oids.drain() {
+            self.dynamic_types.remove(&oid);
+        }
+        had_cached
+    }
+}
+
+fn is_text_only(pg_type: &PgType) -> bool {
+    TEXT_ONLY_NAMES.contains(&pg_type.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pg_type(oid: i32, name: &str, typtype: u8, elem: i32, base_type: i32) -> PgType {
+        PgType {
+            oid,
+            name: name.to_string(),
+            typtype,
+            elem,
+            base_type,
+            relid: 0,
+            namespace: 2200,
+            range_subtype: 0,
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_stacked_domains() {
+        let mut registry = Registry::new();
+        let work_email = pg_type(16401, "work_email", b'd', 0, 16400);
+        let work_emails = pg_type(16402, "_work_email", b'b', 16401, 0);
+        // The domain work_email is based on isn’t known yet.
+        assert_eq!(
+            registry.add_types(vec![work_email, work_emails]),
+            vec![16400]
+        );
+        assert!(!registry.decodes_binary(16402));
+
+        let email = pg_type(16400, "email", b'd', 0, 25);
+        assert!(registry.add_types(vec![email]).is_empty());
+        assert!(registry.decodes_binary(16402));
+        let value = registry
+            .find(16401)
+            .decode(b"root@cas.cat", &registry)
+            .unwrap();
+        assert_eq!(value, Value::Text("root@cas.cat".to_string()));
+    }
+
+    #[test]
+    fn test_record_field_not_looked_up() {
+        let mut registry = Registry::new();
+        // A record with one field, the enum value 'barrel', of a type that isn’t built in.
+        let mut record = 1i32.to_be_bytes().to_vec();
+        record.extend(16410i32.to_be_bytes());
+        record.extend(6i32.to_be_bytes());
+        record.extend(b"barrel");
+
+        let mut oids = Vec::new();
+        registry.visit_value(RECORD_OID, &record, &mut |oid, _| oids.push(oid));
+        assert_eq!(oids, vec![RECORD_OID, 16410]);
+        assert!(registry
+            .find(RECORD_OID)
+            .decode(&record, &registry)
+            .is_err());
+
+        registry.add_types(vec![pg_type(16410, "cask_size", b'e', 0, 0)]);
+        let value = registry
+            .find(RECORD_OID)
+            .decode(&record, &registry)
+            .unwrap();
+        assert_eq!(value, Value::Array(vec![Value::Text("barrel".to_string())]));
+    }
+}
@@ -1,18 +1,23 @@
-use std::collections::HashMap;
+use crate::postgres::output::text_search::TextSearchFormat::{self, Text};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Ser {
     Array,
     Bool,
     BigNum,
     BitString,
     Box,
+    Box2d,
+    Box3d,
     Bytes,
     Circle,
     Date,
     EWKB,
+    Enum,
     Float32,
     Float64,
+    Geography,
+    Hstore,
     Inet,
     Int16,
     Int32,
@@ -22,70 +27,189 @@ pub enum Ser {
     Line,
     LineSegment,
     MacAddr,
+    Money,
+    /// A multirange of the type with the oid.
+    Multirange(i32),
+    /// A type with the oid, that casql can’t decode, within a record, decoded as the text the
+    /// server converted it to.
+    OutputText(i32),
     Path,
+    PgLsn,
     Point,
     Polygon,
+    /// A range of the type with the oid.
+    Range(i32),
+    Record,
+    /// A reg* type with the oid, decoded as the name of the catalog row it refers to.
+    RegName(i32),
+    ServerText,
+    Snapshot,
     String,
     Tid,
     Timestamp,
     TimeUnzoned,
     TimeZoned,
+    TsQuery(TextSearchFormat),
+    TsVector(TextSearchFormat),
+    UInt32,
+    UInt64,
     Unknown,
     Uuid,
 }
 
 // https://github.com/postgres/postgres/blob/master/src/include/catalog/pg_type.dat
-pub fn find_serialiser(oid: i32, dynamic_types: &HashMap<i32, String>) -> Ser {
+pub fn find_serialiser(oid: i32) -> Ser {
     // eprintln!("{:?}", oid);
     match oid {
-        16 => Ser::Bool,          // bool
-        17 => Ser::Bytes,         // bytea
-        18 => Ser::String,        // char
-        19 => Ser::String,        // name
-        20 => Ser::Int64,         // int8
-        21 => Ser::Int16,         // int2
-        22 => Ser::Array,         // int2vector
-        23 => Ser::Int32,         // int4
-        24 => Ser::Int32,         // regproc (proc oid)
-        25 => Ser::String,        // text
-        26 => Ser::Int32,         // oid
-        27 => Ser::Tid,           // tid
-        28 => Ser::Int32,         // xid
-        29 => Ser::Int32,         // cid
-        30 => Ser::Array,         // oidvector
-        114 => Ser::Json,         // json
-        142 => Ser::String,       // xml
-        600 => Ser::Point,        // point
-        601 => Ser::LineSegment,  // lseg
-        602 => Ser::Path,         // path
-        603 => Ser::Box,          // box
-        604 => Ser::Polygon,      // polygon
-        628 => Ser::Line,         // line
-        650 => Ser::Inet,         // cidr
-        718 => Ser::Circle,       // circle
-        700 => Ser::Float32,      // float4
-        701 => Ser::Float64,      // float8
-        774 => Ser::MacAddr,      // macaddr8
-        829 => Ser::MacAddr,      // macaddr
-        869 => Ser::Inet,         // inet
-        1007 => Ser::Array,       // int4[]
-        1042 => Ser::String,      // bpchar
-        1043 => Ser::String,      // varchar
-        1082 => Ser::Date,        // date
-        1083 => Ser::TimeUnzoned, // time
-        1114 => Ser::Timestamp,   // timestamp
-        1184 => Ser::Timestamp,   // timestamptz
-        1186 => Ser::Interval,    // interval
-        1266 => Ser::TimeZoned,   // timetz
-        1560 => Ser::BitString,   // bit
-        1562 => Ser::BitString,   // varbit
-        1700 => Ser::BigNum,      // numeric
-        2950 => Ser::Uuid,        // uuid
-        3802 => Ser::Json,        // jsonb
-        4072 => Ser::String,      // jsonpath
-        _ => match dynamic_types.get(&oid).map(|typname| typname.as_str()) {
-            Some("geometry") => Ser::EWKB,
-            _ => Ser::Unknown,
-        },
+        16 => Ser::Bool,               // bool
+        17 => Ser::Bytes,              // bytea
+        18 => Ser::String,             // char
+        19 => Ser::String,             // name
+        20 => Ser::Int64,              // int8
+        21 => Ser::Int16,              // int2
+        22 => Ser::Array,              // int2vector
+        23 => Ser::Int32,              // int4
+        24 => Ser::UInt32,             // regproc (proc oid)
+        25 => Ser::String,             // text
+        26 => Ser::UInt32,             // oid
+        27 => Ser::Tid,                // tid
+        28 => Ser::UInt32,             // xid
+        29 => Ser::UInt32,             // cid
+        30 => Ser::Array,              // oidvector
+        114 => Ser::Json,              // json
+        142 => Ser::String,            // xml
+        143 => Ser::Array,             // xml[]
+        199 => Ser::Array,             // json[]
+        271 => Ser::Array,             // xid8[]
+        600 => Ser::Point,             // point
+        601 => Ser::LineSegment,       // lseg
+        602 => Ser::Path,              // path
+        603 => Ser::Box,               // box
+        604 => Ser::Polygon,           // polygon
+        628 => Ser::Line,              // line
+        629 => Ser::Array,             // line[]
+        650 => Ser::Inet,              // cidr
+        651 => Ser::Array,             // cidr[]
+        700 => Ser::Float32,           // float4
+        701 => Ser::Float64,           // float8
+        705 => Ser::String,            // unknown
+        718 => Ser::Circle,            // circle
+        719 => Ser::Array,             // circle[]
+        774 => Ser::MacAddr,           // macaddr8
+        775 => Ser::Array,             // macaddr8[]
+        790 => Ser::Money,             // money
+        791 => Ser::Array,             // money[]
+        829 => Ser::MacAddr,           // macaddr
+        869 => Ser::Inet,              // inet
+        1000 => Ser::Array,            // bool[]
+        1001 => Ser::Array,            // bytea[]
+        1002 => Ser::Array,            // char[]
+        1003 => Ser::Array,            // name[]
+        1005 => Ser::Array,            // int2[]
+        1006 => Ser::Array,            // int2vector[]
+        1007 => Ser::Array,            // int4[]
+        1008 => Ser::Array,            // regproc[]
+        1009 => Ser::Array,            // text[]
+        1010 => Ser::Array,            // tid[]
+        1011 => Ser::Array,            // xid[]
+        1012 => Ser::Array,            // cid[]
+        1013 => Ser::Array,            // oidvector[]
+        1014 => Ser::Array,            // bpchar[]
+        1015 => Ser::Array,            // varchar[]
+        1016 => Ser::Array,            // int8[]
+        1017 => Ser::Array,            // point[]
+        1018 => Ser::Array,            // lseg[]
+        1019 => Ser::Array,            // path[]
+        1020 => Ser::Array,            // box[]
+        1021 => Ser::Array,            // float4[]
+        1022 => Ser::Array,            // float8[]
+        1027 => Ser::Array,            // polygon[]
+        1028 => Ser::Array,            // oid[]
+        1040 => Ser::Array,            // macaddr[]
+        1041 => Ser::Array,            // inet[]
+        1042 => Ser::String,           // bpchar
+        1043 => Ser::String,           // varchar
+        1082 => Ser::Date,             // date
+        1083 => Ser::TimeUnzoned,      // time
+        1114 => Ser::Timestamp,        // timestamp
+        1115 => Ser::Array,            // timestamp[]
+        1182 => Ser::Array,            // date[]
+        1183 => Ser::Array,            // time[]
+        1184 => Ser::Timestamp,        // timestamptz
+        1185 => Ser::Array,            // timestamptz[]
+        1186 => Ser::Interval,         // interval
+        1187 => Ser::Array,            // interval[]
+        1231 => Ser::Array,            // numeric[]
+        1266 => Ser::TimeZoned,        // timetz
+        1270 => Ser::Array,            // timetz[]
+        1560 => Ser::BitString,        // bit
+        1561 => Ser::Array,            // bit[]
+        1562 => Ser::BitString,        // varbit
+        1563 => Ser::Array,            // varbit[]
+        1700 => Ser::BigNum,           // numeric
+        2202 => Ser::UInt32,           // regprocedure
+        2203 => Ser::UInt32,           // regoper
+        2204 => Ser::UInt32,           // regoperator
+        2205 => Ser::UInt32,           // regclass
+        2206 => Ser::UInt32,           // regtype
+        2207 => Ser::Array,            // regprocedure[]
+        2208 => Ser::Array,            // regoper[]
+        2209 => Ser::Array,            // regoperator[]
+        2210 => Ser::Array,            // regclass[]
+        2211 => Ser::Array,            // regtype[]
+        2249 => Ser::Record,           // record
+        2287 => Ser::Array,            // record[]
+        2949 => Ser::Array,            // txid_snapshot[]
+        2950 => Ser::Uuid,             // uuid
+        2951 => Ser::Array,            // uuid[]
+        2970 => Ser::Snapshot,         // txid_snapshot
+        3220 => Ser::PgLsn,            // pg_lsn
+        3221 => Ser::Array,            // pg_lsn[]
+        3614 => Ser::TsVector(Text),   // tsvector
+        3615 => Ser::TsQuery(Text),    // tsquery
+        3643 => Ser::Array,            // tsvector[]
+        3645 => Ser::Array,            // tsquery[]
+        3734 => Ser::UInt32,           // regconfig
+        3735 => Ser::Array,            // regconfig[]
+        3769 => Ser::UInt32,           // regdictionary
+        3770 => Ser::Array,            // regdictionary[]
+        3802 => Ser::Json,             // jsonb
+        3807 => Ser::Array,            // jsonb[]
+        3904 => Ser::Range(23),        // int4range
+        3905 => Ser::Array,            // int4range[]
+        3906 => Ser::Range(1700),      // numrange
+        3907 => Ser::Array,            // numrange[]
+        3908 => Ser::Range(1114),      // tsrange
+        3909 => Ser::Array,            // tsrange[]
+        3910 => Ser::Range(1184),      // tstzrange
+        3911 => Ser::Array,            // tstzrange[]
+        3912 => Ser::Range(1082),      // daterange
+        3913 => Ser::Array,            // daterange[]
+        3926 => Ser::Range(20),        // int8range
+        3927 => Ser::Array,            // int8range[]
+        4072 => Ser::String,           // jsonpath
+        4073 => Ser::Array,            // jsonpath[]
+        4089 => Ser::UInt32,           // regnamespace
+        4090 => Ser::Array,            // regnamespace[]
+        4096 => Ser::UInt32,           // regrole
+        4097 => Ser::Array,            // regrole[]
+        4191 => Ser::UInt32,           // regcollation
+        4192 => Ser::Array,            // regcollation[]
+        4451 => Ser::Multirange(23),   // int4multirange
+        4532 => Ser::Multirange(1700), // nummultirange
+        4533 => Ser::Multirange(1114), // tsmultirange
+        4534 => Ser::Multirange(1184), // tstzmultirange
+        4535 => Ser::Multirange(1082), // datemultirange
+        4536 => Ser::Multirange(20),   // int8multirange
+        5038 => Ser::Snapshot,         // pg_snapshot
+        5039 => Ser::Array,            // pg_snapshot[]
+        5069 => Ser::UInt64,           // xid8
+        6150 => Ser::Array,            // int4multirange[]
+        6151 => Ser::Array,            // nummultirange[]
+        6152 => Ser::Array,            // tsmultirange[]
+        6153 => Ser::Array,            // tstzmultirange[]
+        6155 => Ser::Array,            // datemultirange[]
+        6157 => Ser::Array,            // int8multirange[]
+        _ => Ser::Unknown,
     }
 }
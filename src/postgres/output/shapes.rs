@@ -1,24 +1,16 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
-use std::io::Write;
-
-const LEFT_SQUARE: &[u8] = "[".as_bytes();
-const RIGHT_SQUARE: &[u8] = "]".as_bytes();
-const COMMA: &[u8] = ",".as_bytes();
+use crate::postgres::output::value::Value;
 
 /// Given:
 /// f64: x
 /// f64: y
 ///
-/// Writes:
-/// coordinate array [x, y]
-pub fn serialise_point<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Point (x, y)
+pub fn decode_point(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut point = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(out, "[{},{}]", point.f64(), point.f64())?;
-    Ok(())
+    Ok(Value::Point(point.f64(), point.f64()))
 }
 
 /// Given:
@@ -27,21 +19,11 @@ where
 /// f64: B - y coefficient
 /// f64: C - constant
 ///
-/// Writes:
-/// A string representation of the linear equation, "Ax + By + C = 0".
-pub fn serialise_line<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Line (A, B, C), of the linear equation "Ax + By + C = 0".
+pub fn decode_line(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut line = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(
-        out,
-        "\"{}x + {}y + {} = 0\"",
-        line.f64(),
-        line.f64(),
-        line.f64()
-    )?;
-    Ok(())
+    Ok(Value::Line(line.f64(), line.f64(), line.f64()))
 }
 
 /// Given:
@@ -50,22 +32,11 @@ where
 /// f64: x2
 /// f64: y2
 ///
-/// Writes:
-/// [[x1, y1],[x2, y2]]
-pub fn serialise_line_segment<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// Points [(x1, y1), (x2, y2)]
+pub fn decode_line_segment(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut lseg = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(
-        out,
-        "[[{},{}],[{},{}]]",
-        lseg.f64(),
-        lseg.f64(),
-        lseg.f64(),
-        lseg.f64()
-    )?;
-    Ok(())
+    Ok(Value::Points(read_points(&mut lseg, 2)))
 }
 
 /// Given:
@@ -73,21 +44,11 @@ where
 /// f64: centre y
 /// f64: radius
 ///
-/// Writes:
-/// [[x, y], radius]
-pub fn serialise_circle<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Circle (x, y, radius)
+pub fn decode_circle(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut circle = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(
-        out,
-        "[[{},{}],{}]",
-        circle.f64(),
-        circle.f64(),
-        circle.f64(),
-    )?;
-    Ok(())
+    Ok(Value::Circle(circle.f64(), circle.f64(), circle.f64()))
 }
 
 /// Given:
@@ -96,38 +57,24 @@ where
 /// f64: x2
 /// f64: y2
 ///
-/// Writes:
-/// [[x1, y1],[x2, y2]] where x1,y1 is the upper right point, and x2,y2 is the lower left point.
-pub fn serialise_box<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// Points [(x1, y1), (x2, y2)] where x1,y1 is the upper right point, and x2,y2 is the lower left
+/// point.
+pub fn decode_box(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut box_pts = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    write!(
-        out,
-        "[[{},{}],[{},{}]]",
-        box_pts.f64(),
-        box_pts.f64(),
-        box_pts.f64(),
-        box_pts.f64()
-    )?;
-    Ok(())
+    Ok(Value::Points(read_points(&mut box_pts, 2)))
 }
 
 /// Given:
 /// i32: point count
 /// [f64, f64]: points
 ///
-/// Writes:
-/// [[f64, f64], ...].
-pub fn serialise_polygon<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// Points [(f64, f64), ...].
+pub fn decode_polygon(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut poly = BinaryReader::from(bytes, ByteOrder::BigEndian);
     let count = poly.i32();
-    write_coords(&mut poly, &[count, 2], out)?;
-    Ok(())
+    Ok(Value::Points(read_points(&mut poly, count)))
 }
 
 /// Given:
@@ -135,53 +82,16 @@ where
 /// i32: point count
 /// [f64, f64]: points
 ///
-/// Writes:
-/// [[f64, f64], ...] Unlike psql, doesn’t distinguish between open and closed paths, and both are
-/// printed as an array of points.
-pub fn serialise_path<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// Points [(f64, f64), ...] Unlike psql, doesn’t distinguish between open and closed paths, and
+/// both are given as a list of points.
+pub fn decode_path(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut path = BinaryReader::from(bytes, ByteOrder::BigEndian);
     let _open = path.u8() == 1;
     let count = path.i32();
-    write_coords(&mut path, &[count, 2], out)?;
-    Ok(())
+    Ok(Value::Points(read_points(&mut path, count)))
 }
 
-/// Like write_array_elements, but the size isn’t encoded, because they’re always f64s.
-fn write_coords<Out>(
-    bytes: &mut BinaryReader,
-    dimensions: &[i32],
-    out: &mut Out,
-) -> Result<(), CasErr>
-where
-    Out: Write,
-{
-    if dimensions.len() == 1 {
-        let mut first = true;
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..dimensions[0] {
-            if first {
-                first = false
-            } else {
-                out.write(COMMA)?;
-            }
-            write!(out, "{}", bytes.f64())?;
-        }
-        out.write(RIGHT_SQUARE)?;
-    } else {
-        let mut first = true;
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..dimensions[0] {
-            if first {
-                first = false
-            } else {
-                out.write(COMMA)?;
-            }
-            write_coords(bytes, &dimensions[1..dimensions.len()], out)?;
-        }
-        out.write(RIGHT_SQUARE)?;
-    }
-    Ok(())
+fn read_points(bytes: &mut BinaryReader, count: i32) -> Vec<(f64, f64)> {
+    (0..count).map(|_| (bytes.f64(), bytes.f64())).collect()
 }
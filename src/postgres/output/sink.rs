@@ -0,0 +1,27 @@
+use crate::cas_err::CasErr;
+use crate::postgres::output::value::Value;
+
+/// Receives the results of a query as they are decoded, and writes them in some format. The rows
+/// are passed one field at a time, so that they don’t need to be held in memory.
+///
+/// For each result the methods are called in the order:
+/// ```text
+/// begin_result (begin_row (field_name value)* end_row)* end_result
+/// ```
+pub trait Sink {
+    /// Called before the first row, with the names of the columns.
+    fn begin_result(&mut self, columns: &[String]) -> Result<(), CasErr>;
+
+    fn begin_row(&mut self) -> Result<(), CasErr>;
+
+    /// Called before each value, with the name of its column.
+    fn field_name(&mut self, name: &str) -> Result<(), CasErr>;
+
+    /// Called with each decoded value, which is Value::Null for nulls.
+    fn value(&mut self, value: &Value) -> Result<(), CasErr>;
+
+    fn end_row(&mut self) -> Result<(), CasErr>;
+
+    /// Called after the last row. Sinks that buffer their output should flush it here.
+    fn end_result(&mut self) -> Result<(), CasErr>;
+}
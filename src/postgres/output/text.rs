@@ -1,10 +1,68 @@
+use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
+use crate::postgres::output::registry::Registry;
+use crate::postgres::output::value::Value;
 use std::io::Write;
 
 const ESCAPE: &[u8] = "\\".as_bytes();
 const BACKSLASH: &[u8] = "\\".as_bytes();
 const DOUBLE_QUOTE: &[u8] = "\"".as_bytes();
 
+/// Given:
+/// u8[]: bytes representing UTF-8 characters
+/// Returns:
+/// a Text, without any leading SOH
+pub fn decode_str(bytes: &[u8]) -> Result<Value, CasErr> {
+    let chars = bytes.iter().copied().filter(|byte| *byte != 1).collect();
+    let text = String::from_utf8(chars).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Text(text))
+}
+
+/// Given:
+/// u8[]: an enum’s label, which is its binary format, or a value in the server’s text format, for
+/// types without a binary serialiser
+/// Returns:
+/// a Text, as the server wrote it
+pub fn decode_utf8(bytes: &[u8]) -> Result<Value, CasErr> {
+    let text = std::str::from_utf8(bytes).map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+    Ok(Value::Text(text.to_owned()))
+}
+
+/// Given:
+/// u8[]: a value of a type without a serialiser, in its binary format, from within a record
+/// Returns:
+/// a Text of the server’s output for it, if it could convert it, or else Unknown, of the bytes
+pub fn decode_output_text(bytes: &[u8], oid: i32, registry: &Registry) -> Result<Value, CasErr> {
+    match registry.output_text(oid, bytes) {
+        Some(text) => Ok(Value::Text(text.to_owned())),
+        None => Ok(Value::Unknown(bytes.to_vec())),
+    }
+}
+
+/// Given:
+/// i32: number of pairs
+/// [i32, u8[], i32, u8[]]: the size and bytes of each key, then of its value, -1 for null
+/// Returns:
+/// a Record of the keys and their Text values, which is written as a JSON object
+pub fn decode_hstore(bytes: &[u8]) -> Result<Value, CasErr> {
+    let mut hstore = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let pair_count = hstore.i32();
+    let mut pairs = Vec::with_capacity(pair_count as usize);
+    for _ in 0..pair_count {
+        let key_size = hstore.i32();
+        let key = std::str::from_utf8(hstore.byte_slice(key_size as usize))
+            .map_err(|e| CasErr::Utf8Err(e.to_string()))?;
+        let value_size = hstore.i32();
+        let value = if value_size == -1 {
+            Value::Null
+        } else {
+            decode_utf8(hstore.byte_slice(value_size as usize))?
+        };
+        pairs.push((key.to_owned(), value));
+    }
+    Ok(Value::Record(pairs))
+}
+
 /// Given:
 /// u8[]: bytes representing UTF-8 characters
 /// Writes:
@@ -0,0 +1,334 @@
+use crate::binary_reader::{BinaryReader, ByteOrder};
+use crate::cas_err::CasErr;
+use crate::postgres::output::value::Value;
+use std::str::FromStr;
+
+// https://github.com/postgres/postgres/blob/master/src/include/tsearch/ts_type.h
+const QI_VAL: u8 = 1;
+const QI_OPR: u8 = 2;
+const OP_NOT: u8 = 1;
+const OP_AND: u8 = 2;
+const OP_OR: u8 = 3;
+const OP_PHRASE: u8 = 4;
+
+/// How tsvector and tsquery values are decoded: as the text Postgres writes for them, or as JSON
+/// that doesn’t need to be parsed again.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TextSearchFormat {
+    #[default]
+    Text,
+    Structured,
+}
+
+impl FromStr for TextSearchFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TextSearchFormat::Text),
+            "structured" => Ok(TextSearchFormat::Structured),
+            _ => Err(format!(
+                "Invalid text search format: {}. Expected text or structured.",
+                s
+            )),
+        }
+    }
+}
+
+struct Lexeme {
+    text: String,
+    // The position is in the lower 14 bits, and the weight, 3 for A down to 0 for D, in the top 2.
+    positions: Vec<u16>,
+}
+
+enum QueryItem {
+    Operand {
+        text: String,
+        // A bit for each weight: 8 for A, 4 for B, 2 for C and 1 for D.
+        weights: u8,
+        prefix: bool,
+    },
+    Not(Box<QueryItem>),
+    Operator {
+        operator: u8,
+        distance: i16,
+        left: Box<QueryItem>,
+        right: Box<QueryItem>,
+    },
+}
+
+/// Given:
+/// i32: number of lexemes
+/// [c_str, i16, u16[]]: each lexeme, then its number of positions, and each position with its
+/// weight
+///
+/// Returns:
+/// Text, as Postgres writes it, e.g. 'cat':1A,4 'fat':2, or an Array with an object for each
+/// lexeme, e.g. {"lexeme":"cat","positions":[1,4],"weights":"AD"}, with a weight for each position
+pub fn decode_tsvector(bytes: &[u8], format: TextSearchFormat) -> Result<Value, CasErr> {
+    let mut rdr = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let lexeme_count = rdr.i32();
+    let mut lexemes = Vec::with_capacity(lexeme_count as usize);
+    for _ in 0..lexeme_count {
+        let text = rdr.c_str();
+        let position_count = rdr.i16();
+        let positions = (0..position_count).map(|_| rdr.i16() as u16).collect();
+        lexemes.push(Lexeme { text, positions });
+    }
+    match format {
+        TextSearchFormat::Text => Ok(Value::Text(tsvector_text(&lexemes))),
+        TextSearchFormat::Structured => Ok(Value::Array(
+            lexemes.into_iter().map(lexeme_value).collect(),
+        )),
+    }
+}
+
+/// Given:
+/// i32: number of items
+/// [u8, ...]: each item, in prefix order, with an operator followed by its right operand and then
+/// its left. An operand is u8 weights, u8 1 if it is a prefix and a c_str lexeme, and an operator
+/// is a u8 for which operator it is, followed by an i16 distance if it is a phrase operator.
+///
+/// Returns:
+/// Text, as Postgres writes it, e.g. 'fat' & !'rat':*A, or the tree of items, with each operand as
+/// an object like {"lexeme":"rat","weights":"A","prefix":true}, and each operator as an object like
+/// {"operator":"&","operands":[...]}. An empty query is Null.
+pub fn decode_tsquery(bytes: &[u8], format: TextSearchFormat) -> Result<Value, CasErr> {
+    let mut rdr = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let item_count = rdr.i32();
+    if item_count == 0 {
+        return match format {
+            TextSearchFormat::Text => Ok(Value::Text(String::new())),
+            TextSearchFormat::Structured => Ok(Value::Null),
+        };
+    }
+    let query = read_query_item(&mut rdr)?;
+    match format {
+        TextSearchFormat::Text => {
+            let mut text = String::new();
+            write_query_item(&query, -1, false, &mut text);
+            Ok(Value::Text(text))
+        }
+        TextSearchFormat::Structured => Ok(query_item_value(query)),
+    }
+}
+
+fn read_query_item(rdr: &mut BinaryReader) -> Result<QueryItem, CasErr> {
+    match rdr.u8() {
+        QI_VAL => {
+            let weights = rdr.u8();
+            let prefix = rdr.u8() == 1;
+            let text = rdr.c_str();
+            Ok(QueryItem::Operand {
+                text,
+                weights,
+                prefix,
+            })
+        }
+        QI_OPR => match rdr.u8() {
+            OP_NOT => Ok(QueryItem::Not(Box::new(read_query_item(rdr)?))),
+            operator @ (OP_AND | OP_OR | OP_PHRASE) => {
+                let distance = if operator == OP_PHRASE { rdr.i16() } else { 0 };
+                let right = read_query_item(rdr)?;
+                let left = read_query_item(rdr)?;
+                Ok(QueryItem::Operator {
+                    operator,
+                    distance,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            operator => Err(CasErr::DecodeErr(format!(
+                "Unrecognised tsquery operator: {}",
+                operator
+            ))),
+        },
+        item_type => Err(CasErr::DecodeErr(format!(
+            "Unrecognised tsquery item type: {}",
+            item_type
+        ))),
+    }
+}
+
+fn tsvector_text(lexemes: &[Lexeme]) -> String {
+    let mut text = String::new();
+    for (idx, lexeme) in lexemes.iter().enumerate() {
+        if idx > 0 {
+            text.push(' ');
+        }
+        write_quoted(&lexeme.text, &mut text);
+        for (idx, position) in lexeme.positions.iter().enumerate() {
+            text.push(if idx == 0 { ':' } else { ',' });
+            text.push_str(&(position & 0x3fff).to_string());
+            if position >> 14 != 0 {
+                text.push(position_weight(*position));
+            }
+        }
+    }
+    text
+}
+
+fn lexeme_value(lexeme: Lexeme) -> Value {
+    let positions = lexeme
+        .positions
+        .iter()
+        .map(|position| Value::Int((position & 0x3fff) as i64))
+        .collect();
+    let weights = lexeme
+        .positions
+        .iter()
+        .map(|position| position_weight(*position))
+        .collect();
+    Value::Record(vec![
+        ("lexeme".to_string(), Value::Text(lexeme.text)),
+        ("positions".to_string(), Value::Array(positions)),
+        ("weights".to_string(), Value::Text(weights)),
+    ])
+}
+
+fn position_weight(position: u16) -> char {
+    match position >> 14 {
+        3 => 'A',
+        2 => 'B',
+        1 => 'C',
+        _ => 'D',
+    }
+}
+
+fn operand_weights(weights: u8) -> String {
+    [(8, 'A'), (4, 'B'), (2, 'C'), (1, 'D')]
+        .iter()
+        .filter(|(bit, _)| weights & bit != 0)
+        .map(|(_, weight)| *weight)
+        .collect()
+}
+
+fn operator_text(operator: u8, distance: i16) -> String {
+    match operator {
+        OP_NOT => "!".to_string(),
+        OP_AND => "&".to_string(),
+        OP_OR => "|".to_string(),
+        _ if distance == 1 => "<->".to_string(),
+        _ => format!("<{}>", distance),
+    }
+}
+
+// The same as Postgres’ tsearch_op_priority.
+fn operator_priority(operator: u8) -> i8 {
+    match operator {
+        OP_NOT => 4,
+        OP_AND => 2,
+        OP_OR => 1,
+        _ => 3,
+    }
+}
+
+/// Writes the item the way Postgres’ tsquery output does, only adding parentheses where the
+/// priority of the operators needs them.
+fn write_query_item(
+    item: &QueryItem,
+    parent_priority: i8,
+    right_of_phrase: bool,
+    text: &mut String,
+) {
+    match item {
+        QueryItem::Operand {
+            text: lexeme,
+            weights,
+            prefix,
+        } => {
+            write_quoted(lexeme, text);
+            if *weights != 0 || *prefix {
+                text.push(':');
+                if *prefix {
+                    text.push('*');
+                }
+                text.push_str(&operand_weights(*weights));
+            }
+        }
+        QueryItem::Not(operand) => {
+            let priority = operator_priority(OP_NOT);
+            let parenthesised = priority < parent_priority;
+            if parenthesised {
+                text.push_str("( ");
+            }
+            text.push('!');
+            write_query_item(operand, priority, false, text);
+            if parenthesised {
+                text.push_str(" )");
+            }
+        }
+        QueryItem::Operator {
+            operator,
+            distance,
+            left,
+            right,
+        } => {
+            let priority = operator_priority(*operator);
+            let parenthesised =
+                priority < parent_priority || (*operator == OP_PHRASE && right_of_phrase);
+            if parenthesised {
+                text.push_str("( ");
+            }
+            write_query_item(left, priority, false, text);
+            text.push(' ');
+            text.push_str(&operator_text(*operator, *distance));
+            text.push(' ');
+            write_query_item(right, priority, *operator == OP_PHRASE, text);
+            if parenthesised {
+                text.push_str(" )");
+            }
+        }
+    }
+}
+
+fn query_item_value(item: QueryItem) -> Value {
+    match item {
+        QueryItem::Operand {
+            text,
+            weights,
+            prefix,
+        } => Value::Record(vec![
+            ("lexeme".to_string(), Value::Text(text)),
+            ("weights".to_string(), Value::Text(operand_weights(weights))),
+            ("prefix".to_string(), Value::Bool(prefix)),
+        ]),
+        QueryItem::Not(operand) => Value::Record(vec![
+            (
+                "operator".to_string(),
+                Value::Text(operator_text(OP_NOT, 0)),
+            ),
+            (
+                "operands".to_string(),
+                Value::Array(vec![query_item_value(*operand)]),
+            ),
+        ]),
+        QueryItem::Operator {
+            operator,
+            distance,
+            left,
+            right,
+        } => Value::Record(vec![
+            (
+                "operator".to_string(),
+                Value::Text(operator_text(operator, distance)),
+            ),
+            (
+                "operands".to_string(),
+                Value::Array(vec![query_item_value(*left), query_item_value(*right)]),
+            ),
+        ]),
+    }
+}
+
+/// Quotes a lexeme, doubling any quotes and backslashes in it.
+fn write_quoted(lexeme: &str, text: &mut String) {
+    text.push('\'');
+    for c in lexeme.chars() {
+        if c == '\'' || c == '\\' {
+            text.push(c);
+        }
+        text.push(c);
+    }
+    text.push('\'');
+}
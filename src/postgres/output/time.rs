@@ -1,9 +1,8 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
+use crate::postgres::output::value::Value;
 use std::io::Write;
 
-const DOUBLE_QUOTE: &[u8] = "\"".as_bytes();
-
 struct Time {
     hours: i8,
     minutes: i8,
@@ -32,57 +31,46 @@ impl Time {
 /// Given:
 /// i32: days after 2000-01-01, negative numbers are days before
 ///
-/// Writes:
-/// date-string YYYY-MM-DD
+/// Returns:
+/// a Date, YYYY-MM-DD
 ///
 /// The libraries time and chrono only handle +/- 10000 years.
-pub fn serialise_date<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+pub fn decode_date(bytes: &[u8]) -> Result<Value, CasErr> {
     let days = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-    out.write(DOUBLE_QUOTE)?;
-    write_date(days, out)?;
-    out.write(DOUBLE_QUOTE)?;
-    Ok(())
+    let mut out = Vec::new();
+    write_date(days, &mut out)?;
+    Ok(Value::Date(to_string(out)?))
 }
 
 /// Given:
 /// i64: microseconds after midnight
 ///
-/// Writes:
-/// time string HH:MM:SS, where seconds may have a fractional component
-pub fn serialise_time_unzoned<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+/// Returns:
+/// a Time, HH:MM:SS, where seconds may have a fractional component
+pub fn decode_time_unzoned(bytes: &[u8]) -> Result<Value, CasErr> {
     let microseconds = i64::from_be_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
     ]);
-    out.write(DOUBLE_QUOTE)?;
-    write_time(microseconds, out)?;
-    out.write(DOUBLE_QUOTE)?;
-    Ok(())
+    let mut out = Vec::new();
+    write_time(microseconds, &mut out)?;
+    Ok(Value::Time(to_string(out)?))
 }
 
 /// Given:
 /// i64: microseconds after midnight
 /// i32: offset seconds, seconds behind UTC. -01:00 is 3600 and +01:00 is -3600.
 ///
-/// Writes:
-/// time string HH:MM:SS+HH:MM(:SS), where the time-seconds may have a fractional component. The
+/// Returns:
+/// a Time, HH:MM:SS+HH:MM(:SS), where the time-seconds may have a fractional component. The
 /// offset seconds are optional, and never fractional. Postgres doesn’t distinguish between GMT and
 /// UTC, so 0-offsets are always written +00:00, not as Z.
 ///
-pub fn serialise_time_zoned<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+pub fn decode_time_zoned(bytes: &[u8]) -> Result<Value, CasErr> {
     let microseconds = i64::from_be_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
     ]);
-    out.write(DOUBLE_QUOTE)?;
-    write_time(microseconds, out)?;
+    let mut out = Vec::new();
+    write_time(microseconds, &mut out)?;
 
     let mut offset_seconds = -i32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
     let offset_hours = offset_seconds / 3600;
@@ -99,15 +87,14 @@ where
         write!(out, "{:+03}:{:02}", offset_hours, offset_minutes)
     }?;
 
-    out.write(DOUBLE_QUOTE)?;
-    Ok(())
+    Ok(Value::Time(to_string(out)?))
 }
 
 /// Given:
 /// i64: microseconds after 2000-01-01, negative numbers are microseconds before
 ///
-/// Writes:
-/// UTC datetime-string YYYY-MM-DDTHH:MM:SSZ.
+/// Returns:
+/// a Timestamp, the UTC datetime-string YYYY-MM-DDTHH:MM:SSZ.
 ///
 /// For timestamptz, it is still stored internally as UTC, in microseconds. At insertion Postgres
 /// converts from the specified timezone to UTC. If no timezone is specified it uses the server time
@@ -116,10 +103,7 @@ where
 /// because 1. I’m lazy and it’s less code to write and 2. it doesn’t make sense, because the
 /// server time zone is unrelated to the original input data’s time zone. It should be up to the
 /// client to decide with which timezone to interpret the timestamp. Everything is Zulu time.
-pub fn serialise_datetime<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+pub fn decode_datetime(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut microseconds = i64::from_be_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
     ]);
@@ -132,13 +116,12 @@ where
         microseconds = microseconds + us_per_day;
     }
 
-    out.write(DOUBLE_QUOTE)?;
-    write_date(days as i32, out)?;
-    out.write("T".as_bytes())?;
-    write_time(microseconds, out)?;
-    out.write("Z".as_bytes())?;
-    out.write(DOUBLE_QUOTE)?;
-    Ok(())
+    let mut out = Vec::new();
+    write_date(days as i32, &mut out)?;
+    out.push(b'T');
+    write_time(microseconds, &mut out)?;
+    out.push(b'Z');
+    Ok(Value::Timestamp(to_string(out)?))
 }
 
 /// Given:
@@ -146,8 +129,9 @@ where
 /// i32: days
 /// i32: months
 ///
-/// Writes:
-/// ISO 8601 compact period string, e.g. "P3Y6M4DT12H30M5S". Empty interval is "P0D".
+/// Returns:
+/// an Interval, the ISO 8601 compact period string, e.g. "P3Y6M4DT12H30M5S". Empty interval is
+/// "P0D".
 ///
 /// ISO 8601 doesn’t specify negative intervals, but I’m adding negative signs where applicable.
 ///
@@ -155,25 +139,21 @@ where
 /// description. For example, the year in  <date> + '1 year - 1 day' may be a leap year, if the
 /// date is the year before one. The upshot is that I can reduce some parts, like months to years,
 /// but not others, like months to days. Days may have more/fewer hours at daylight savings transitions.
-pub fn serialise_duration<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
-where
-    Out: Write,
-{
+pub fn decode_duration(bytes: &[u8]) -> Result<Value, CasErr> {
     let mut interval = BinaryReader::from(bytes, ByteOrder::BigEndian);
     let microseconds = interval.i64();
     let days = interval.i32();
     let mut months = interval.i32();
 
     if days == 0 && months == 0 && microseconds == 0 {
-        out.write("\"P0D\"".as_bytes())?;
-        return Ok(());
+        return Ok(Value::Interval("P0D".to_owned()));
     }
 
     let years = months / 12;
     months -= years * 12;
     let time = Time::from(microseconds);
 
-    let mut duration_str = "\"P".to_owned();
+    let mut duration_str = "P".to_owned();
     if years != 0 {
         duration_str.push_str(&years.to_string());
         duration_str.push('Y');
@@ -201,9 +181,11 @@ where
         duration_str.push_str(&time.seconds.to_string());
         duration_str.push('S');
     };
-    duration_str.push('"');
-    out.write(duration_str.as_bytes())?;
-    Ok(())
+    Ok(Value::Interval(duration_str))
+}
+
+fn to_string(bytes: Vec<u8>) -> Result<String, CasErr> {
+    String::from_utf8(bytes).map_err(|e| CasErr::Utf8Err(e.to_string()))
 }
 
 /// Given the number of days to add to the Postgres epoch, calculate the date and print it. Does not
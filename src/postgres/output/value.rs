@@ -0,0 +1,314 @@
+use crate::binary_reader::{BinaryReader, ByteOrder};
+use crate::cas_err::CasErr;
+use crate::postgres::output::registry::{Registry, Serialiser};
+use crate::postgres::output::ser::Ser;
+use crate::postgres::output::{
+    binary, ekwb, json, net, nums, range, record, shapes, text, text_search, time,
+};
+use crate::postgres::row_iter::RowIter;
+use std::sync::Arc;
+
+/// A decoded Postgres value. The JSON output is written from these, so each variant corresponds to
+/// one way of writing a value. Types without a Rust equivalent, such as dates or UUIDs, hold the
+/// string they are written as.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Float32(f32),
+    /// A decimal string, or "NaN".
+    Numeric(String),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// A string of 1s and 0s.
+    BitString(String),
+    Date(String),
+    Time(String),
+    Timestamp(String),
+    Interval(String),
+    Uuid(String),
+    Inet(String),
+    MacAddr(String),
+    /// Compact JSON text.
+    Json(String),
+    /// A (block, offset) tuple.
+    Tid(i32, i16),
+    Point(f64, f64),
+    /// The points of a line segment, box, path or polygon.
+    Points(Vec<(f64, f64)>),
+    /// The centre x and y, and the radius.
+    Circle(f64, f64, f64),
+    /// A, B and C, of Ax + By + C = 0.
+    Line(f64, f64, f64),
+    /// GeoJSON text.
+    Geometry(String),
+    /// Multi-dimensional arrays are nested.
+    Array(Vec<Value>),
+    /// The bounds of a range, Null if they are infinite, and whether they are inclusive.
+    Range {
+        lower: Box<Value>,
+        upper: Box<Value>,
+        lower_inc: bool,
+        upper_inc: bool,
+    },
+    EmptyRange,
+    /// The attribute names and values of a composite type.
+    Record(Vec<(String, Value)>),
+    /// The raw bytes of a type that casql can’t decode.
+    Unknown(Vec<u8>),
+}
+
+/// Decodes the binary representation of a value, with the built-in serialiser for its type.
+pub fn decode(bytes: &[u8], serialiser: &Ser, registry: &Registry) -> Result<Value, CasErr> {
+    match serialiser {
+        Ser::Array => decode_array(bytes, registry),
+        Ser::Bool => nums::decode_bool(bytes),
+        Ser::BigNum => nums::decode_bignum(bytes),
+        Ser::BitString => binary::decode_bitstring(bytes),
+        Ser::Box => shapes::decode_box(bytes),
+        Ser::Box2d => ekwb::decode_box2d(bytes),
+        Ser::Box3d => ekwb::decode_box3d(bytes),
+        Ser::Bytes => binary::decode_bytes(bytes),
+        Ser::Circle => shapes::decode_circle(bytes),
+        Ser::Date => time::decode_date(bytes),
+        Ser::EWKB => ekwb::decode_geom(bytes),
+        Ser::Enum | Ser::ServerText => text::decode_utf8(bytes),
+        Ser::Float32 => nums::decode_f32(bytes),
+        Ser::Float64 => nums::decode_f64(bytes),
+        Ser::Geography => ekwb::decode_geography(bytes),
+        Ser::Hstore => text::decode_hstore(bytes),
+        Ser::Inet => net::decode_inet(bytes),
+        Ser::Int16 => nums::decode_i16(bytes),
+        Ser::Int32 => nums::decode_i32(bytes),
+        Ser::Int64 => nums::decode_i64(bytes),
+        Ser::Interval => time::decode_duration(bytes),
+        Ser::Json => json::decode_json(bytes),
+        Ser::Line => shapes::decode_line(bytes),
+        Ser::LineSegment => shapes::decode_line_segment(bytes),
+        Ser::MacAddr => net::decode_mac_addr(bytes),
+        Ser::Money => nums::decode_money(bytes, registry.money_fraction_digits()),
+        Ser::OutputText(oid) => text::decode_output_text(bytes, *oid, registry),
+        Ser::Path => shapes::decode_path(bytes),
+        Ser::PgLsn => nums::decode_lsn(bytes),
+        Ser::Point => shapes::decode_point(bytes),
+        Ser::Multirange(subtype) => range::decode_multirange(bytes, *subtype, registry),
+        Ser::Polygon => shapes::decode_polygon(bytes),
+        Ser::Range(subtype) => range::decode_range(bytes, *subtype, registry),
+        Ser::Record => record::decode_record(bytes, registry),
+        Ser::RegName(oid) => nums::decode_reg_name(bytes, *oid, registry),
+        Ser::Snapshot => nums::decode_snapshot(bytes),
+        Ser::String => text::decode_str(bytes),
+        Ser::Tid => nums::decode_tid(bytes),
+        Ser::Timestamp => time::decode_datetime(bytes),
+        Ser::TimeUnzoned => time::decode_time_unzoned(bytes),
+        Ser::TimeZoned => time::decode_time_zoned(bytes),
+        Ser::TsQuery(format) => text_search::decode_tsquery(bytes, *format),
+        Ser::TsVector(format) => text_search::decode_tsvector(bytes, *format),
+        Ser::UInt32 => nums::decode_u32(bytes),
+        Ser::UInt64 => nums::decode_u64(bytes),
+        Ser::Unknown => Ok(Value::Unknown(bytes.to_vec())),
+        Ser::Uuid => binary::decode_uuid(bytes),
+    }
+}
+
+/// Decodes a value, or a null if there are no bytes.
+pub fn decode_nullable(
+    bytes: Option<&[u8]>,
+    serialiser: &dyn Serialiser,
+    registry: &Registry,
+) -> Result<Value, CasErr> {
+    match bytes {
+        Some(bytes) => serialiser.decode(bytes, registry),
+        None => Ok(Value::Null),
+    }
+}
+
+/// Given:
+/// i32: number of dimensions, 0 for an empty array
+/// i32: 1 if there are nulls
+/// i32: element oid
+/// [i32, i32]: the size and lower bound of each dimension
+/// [i32, u8[]]: the size and bytes of each element, -1 for null
+///
+/// Returns:
+/// an Array, with an Array for each element of the outer dimensions. Arrays are indexed from 1
+/// unless they are given other bounds, as with '[0:1]={1,2}', in which case it is a Record with
+/// the lower bound of each dimension, and the elements.
+fn decode_array(bytes: &[u8], registry: &Registry) -> Result<Value, CasErr> {
+    let mut array = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let n_dims = array.i32();
+    // something to do with nulls
+    let _offset = array.i32();
+    let item_oid = array.i32();
+    let mut counts = Vec::new();
+    let mut lower_bounds = Vec::new();
+    for _ in 0..n_dims {
+        counts.push(array.i32());
+        lower_bounds.push(array.i32());
+    }
+    if counts.is_empty() {
+        return Ok(Value::Array(Vec::new()));
+    }
+    let parser = registry.find(item_oid);
+    let elements = decode_array_elements(&mut array, &counts, parser.as_ref(), registry)?;
+    if lower_bounds.iter().all(|bound| *bound == 1) {
+        Ok(elements)
+    } else {
+        let lower_bounds = lower_bounds
+            .into_iter()
+            .map(|bound| Value::Int(bound as i64))
+            .collect();
+        Ok(Value::Record(vec![
+            ("lower_bounds".to_string(), Value::Array(lower_bounds)),
+            ("elements".to_string(), elements),
+        ]))
+    }
+}
+
+/// Calls f with the element oid, from the array’s header, and the bytes of each element that
+/// isn’t null, in every dimension.
+pub fn visit_array_elements(bytes: &[u8], f: &mut dyn FnMut(i32, &[u8])) {
+    let mut array = BinaryReader::from(bytes, ByteOrder::BigEndian);
+    let n_dims = array.i32();
+    let _offset = array.i32();
+    let item_oid = array.i32();
+    let mut element_count = if n_dims == 0 { 0 } else { 1 };
+    for _ in 0..n_dims {
+        element_count *= array.i32();
+        let _lower_bound = array.i32();
+    }
+    for _ in 0..element_count {
+        let size = array.i32();
+        if size != -1 {
+            f(item_oid, array.byte_slice(size as usize));
+        }
+    }
+}
+
+fn decode_array_elements(
+    bytes: &mut BinaryReader,
+    dimensions: &[i32],
+    serialiser: &dyn Serialiser,
+    registry: &Registry,
+) -> Result<Value, CasErr> {
+    let mut elements = Vec::with_capacity(dimensions[0] as usize);
+    for _ in 0..dimensions[0] {
+        if dimensions.len() == 1 {
+            let size = bytes.i32();
+            if size == -1 {
+                elements.push(Value::Null);
+            } else {
+                elements.push(serialiser.decode(bytes.byte_slice(size as usize), registry)?);
+            }
+        } else {
+            elements.push(decode_array_elements(
+                bytes,
+                &dimensions[1..dimensions.len()],
+                serialiser,
+                registry,
+            )?);
+        }
+    }
+    Ok(Value::Array(elements))
+}
+
+/// Splits a DataRow message into the bytes of each value, None if it is null.
+pub fn split_row(row: &[u8]) -> Vec<Option<&[u8]>> {
+    let mut rdr = BinaryReader::from(row, ByteOrder::BigEndian);
+    // skip discriminator, message size
+    rdr.skip(5);
+    let value_count = rdr.i16();
+    (0..value_count)
+        .map(|_| {
+            let value_len = rdr.i32();
+            if value_len == -1 {
+                None
+            } else {
+                Some(rdr.byte_slice(value_len as usize))
+            }
+        })
+        .collect()
+}
+
+/// A row of decoded values, in the order of the columns.
+#[derive(Clone, Debug)]
+pub struct Row {
+    pub(crate) columns: Arc<[String]>,
+    pub(crate) values: Vec<Value>,
+}
+
+impl Row {
+    /// The column names, which are shared by all the rows of a query.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// The value of the first column with the name, if there is one.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.columns
+            .iter()
+            .position(|name| name == column)
+            .map(|idx| &self.values[idx])
+    }
+}
+
+/// The rows of a query, decoded as they are read from the connection. If it is dropped before the
+/// last row, the rest are read and discarded, so that the connection can be used again.
+pub struct Rows<'stream> {
+    rows: RowIter<'stream>,
+    columns: Arc<[String]>,
+    serialisers: Vec<Arc<dyn Serialiser>>,
+    registry: &'stream Registry,
+}
+
+impl<'stream> Rows<'stream> {
+    pub(crate) fn new(rows: RowIter<'stream>, registry: &'stream Registry) -> Self {
+        let columns = rows.fields.iter().map(|field| field.name.clone()).collect();
+        let serialisers = rows
+            .fields
+            .iter()
+            .map(|field| registry.find_for_field(field))
+            .collect();
+        Rows {
+            rows,
+            columns,
+            serialisers,
+            registry,
+        }
+    }
+
+    /// The column names.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+impl<'stream> Iterator for Rows<'stream> {
+    type Item = Result<Row, CasErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.rows.next()? {
+            Ok(row) => row,
+            Err(err) => return Some(Err(err)),
+        };
+        let values = split_row(&row)
+            .into_iter()
+            .zip(self.serialisers.iter())
+            .map(|(bytes, ser)| decode_nullable(bytes, ser.as_ref(), self.registry))
+            .collect::<Result<Vec<Value>, CasErr>>();
+        Some(values.map(|values| Row {
+            columns: self.columns.clone(),
+            values,
+        }))
+    }
+}
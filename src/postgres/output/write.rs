@@ -1,10 +1,13 @@
 use crate::binary_reader::{BinaryReader, ByteOrder};
 use crate::cas_err::CasErr;
-use crate::postgres::output::ser::{find_serialiser, Ser};
-use crate::postgres::output::{binary, ekwb, json, net, nums, shapes, text, time};
+use crate::postgres::backend_msgs::Field;
+use crate::postgres::output::registry::{Registry, Serialiser};
+use crate::postgres::output::sink::Sink;
+use crate::postgres::output::text;
+use crate::postgres::output::value::Value;
 use crate::postgres::row_iter::RowIter;
-use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 
 const LEFT_SQUARE: &[u8] = "[".as_bytes();
 const LEFT_BRACE: &[u8] = "{".as_bytes();
@@ -14,179 +17,288 @@ const NEW_LINE: &[u8] = "\n".as_bytes();
 const DOUBLE_QUOTE: &[u8] = "\"".as_bytes();
 const COMMA: &[u8] = ",".as_bytes();
 const COLON: &[u8] = ":".as_bytes();
+const EMPTY_RANGE: &[u8] = "\"empty\"".as_bytes();
 const NULL: &[u8] = "null".as_bytes();
+const TRUE: &[u8] = "true".as_bytes();
+const FALSE: &[u8] = "false".as_bytes();
+const NAN: &[u8] = "\"NaN\"".as_bytes();
+const INFINITY: &[u8] = "\"Infinity\"".as_bytes();
+const NEGATIVE_INFINITY: &[u8] = "\"-Infinity\"".as_bytes();
 
-struct JsonField {
-    name: String,
-    serialiser: Ser,
+/// A column of the result, with the serialiser for its type.
+pub struct OutputField {
+    pub name: String,
+    pub serialiser: Arc<dyn Serialiser>,
 }
 
-pub fn write_rows<Out>(
-    rows: RowIter,
-    dynamic_types: &HashMap<i32, String>,
-    out: &mut Out,
-) -> Result<(), CasErr>
+/// Decodes the rows, and passes them to the sink.
+pub fn write_rows<S>(rows: RowIter, registry: &Registry, sink: &mut S) -> Result<(), CasErr>
 where
-    Out: Write,
+    S: Sink + ?Sized,
 {
-    out.write(LEFT_SQUARE)?;
-    let fields: Vec<JsonField> = rows
-        .fields
+    let fields = output_fields(&rows.fields, registry);
+    sink.begin_result(&column_names(&fields))?;
+    for row in rows {
+        write_row(&row?, &fields, registry, sink)?;
+    }
+    sink.end_result()
+}
+
+/// Finds the serialiser for each field, so that the rows can be written one at a time.
+pub fn output_fields(fields: &[Field], registry: &Registry) -> Vec<OutputField> {
+    fields
         .iter()
-        .map(|field| JsonField {
+        .map(|field| OutputField {
             name: field.name.clone(),
-            serialiser: find_serialiser(field.data_type_oid, dynamic_types),
+            serialiser: registry.find_for_field(field),
         })
-        .collect();
-    let mut first = true;
-    for row in rows {
-        if first {
-            first = false
-        } else {
-            out.write(COMMA)?;
-        }
-        write_row(row, &fields, out)?;
-    }
-    out.write(RIGHT_SQUARE)?;
-    out.write(NEW_LINE)?;
-    out.flush()?;
-    Ok(())
+        .collect()
+}
+
+pub fn column_names(fields: &[OutputField]) -> Vec<String> {
+    fields.iter().map(|field| field.name.clone()).collect()
 }
 
-fn write_row<Out>(row: Vec<u8>, fields: &Vec<JsonField>, out: &mut Out) -> Result<(), CasErr>
+/// Decodes a DataRow message, and passes its values to the sink.
+pub fn write_row<S>(
+    row: &[u8],
+    fields: &[OutputField],
+    registry: &Registry,
+    sink: &mut S,
+) -> Result<(), CasErr>
 where
-    Out: Write,
+    S: Sink + ?Sized,
 {
-    let mut rdr = BinaryReader::from(&row, ByteOrder::BigEndian);
+    let mut rdr = BinaryReader::from(row, ByteOrder::BigEndian);
     // skip discriminator, message size, value_count
     rdr.skip(7);
-    out.write(LEFT_BRACE)?;
-    let mut first = true;
-    for idx in 0..fields.len() {
-        if first {
-            first = false
-        } else {
-            out.write(COMMA)?;
-        }
-
-        let field = &fields[idx];
-        out.write(DOUBLE_QUOTE)?;
-        out.write(field.name.as_bytes())?;
-        out.write(DOUBLE_QUOTE)?;
-        out.write(COLON)?;
-
+    sink.begin_row()?;
+    for field in fields {
+        sink.field_name(&field.name)?;
         let value_len = rdr.i32();
         if value_len == -1 {
-            out.write(NULL)?;
+            sink.value(&Value::Null)?;
         } else {
             let value_bytes = rdr.byte_slice(value_len as usize);
-            write_value(value_bytes, &field.serialiser, out)?;
+            sink.value(&field.serialiser.decode(value_bytes, registry)?)?;
         }
     }
-    out.write(RIGHT_BRACE)?;
-    Ok(())
+    sink.end_row()
 }
 
-fn write_value<Out>(bytes: &[u8], serialiser: &Ser, out: &mut Out) -> Result<(), CasErr>
+/// Writes the results as a JSON array, with an object for each row, followed by a new line.
+pub struct JsonSink<Out> {
+    out: Out,
+    first_row: bool,
+    first_field: bool,
+}
+
+impl<Out> JsonSink<Out>
 where
     Out: Write,
 {
-    match serialiser {
-        Ser::Array => write_array(bytes, out),
-        Ser::Bool => nums::serialise_bool(bytes, out),
-        Ser::BigNum => nums::serialise_bignum(bytes, out),
-        Ser::BitString => binary::serialise_bitstring(bytes, out),
-        Ser::Box => shapes::serialise_box(bytes, out),
-        Ser::Bytes => binary::serialise_bytes(bytes, out),
-        Ser::Circle => shapes::serialise_circle(bytes, out),
-        Ser::Date => time::serialise_date(bytes, out),
-        Ser::EWKB => ekwb::serialise_geom(bytes, out),
-        Ser::Float32 => nums::serialise_f32(bytes, out),
-        Ser::Float64 => nums::serialise_f64(bytes, out),
-        Ser::Inet => net::serialise_inet(bytes, out),
-        Ser::Int16 => nums::serialise_i16(bytes, out),
-        Ser::Int32 => nums::serialise_i32(bytes, out),
-        Ser::Int64 => nums::serialise_i64(bytes, out),
-        Ser::Interval => time::serialise_duration(bytes, out),
-        Ser::Json => json::serialise_json(bytes, out),
-        Ser::Line => shapes::serialise_line(bytes, out),
-        Ser::LineSegment => shapes::serialise_line_segment(bytes, out),
-        Ser::MacAddr => net::serialise_mac_addr(bytes, out),
-        Ser::Path => shapes::serialise_path(bytes, out),
-        Ser::Point => shapes::serialise_point(bytes, out),
-        Ser::Polygon => shapes::serialise_polygon(bytes, out),
-        Ser::String => text::serialise_str(bytes, out),
-        Ser::Tid => nums::serialise_tid(bytes, out),
-        Ser::Timestamp => time::serialise_datetime(bytes, out),
-        Ser::TimeUnzoned => time::serialise_time_unzoned(bytes, out),
-        Ser::TimeZoned => time::serialise_time_zoned(bytes, out),
-        Ser::Unknown => {
-            out.write("???".as_bytes())?;
-            Ok(())
+    pub fn new(out: Out) -> Self {
+        JsonSink {
+            out,
+            first_row: true,
+            first_field: true,
         }
-        Ser::Uuid => binary::serialise_uuid(bytes, out),
+    }
+
+    pub fn get_mut(&mut self) -> &mut Out {
+        &mut self.out
+    }
+
+    pub fn into_inner(self) -> Out {
+        self.out
     }
 }
 
-fn write_array<Out>(bytes: &[u8], out: &mut Out) -> Result<(), CasErr>
+impl<Out> Sink for JsonSink<Out>
 where
     Out: Write,
 {
-    let mut array = BinaryReader::from(bytes, ByteOrder::BigEndian);
-    let n_dims = array.i32();
-    // something to do with nulls
-    let _offset = array.i32();
-    let item_oid = array.i32();
-    let mut counts = Vec::new();
-    for _ in 0..n_dims {
-        let vals_count = array.i32();
-        counts.push(vals_count);
-        // I haven't found a case where this doesn't equal 1.
-        let _lower_bounds = array.i32();
+    fn begin_result(&mut self, _columns: &[String]) -> Result<(), CasErr> {
+        self.first_row = true;
+        self.out.write_all(LEFT_SQUARE)?;
+        Ok(())
+    }
+
+    fn begin_row(&mut self) -> Result<(), CasErr> {
+        if self.first_row {
+            self.first_row = false;
+        } else {
+            self.out.write_all(COMMA)?;
+        }
+        self.first_field = true;
+        self.out.write_all(LEFT_BRACE)?;
+        Ok(())
+    }
+
+    fn field_name(&mut self, name: &str) -> Result<(), CasErr> {
+        if self.first_field {
+            self.first_field = false;
+        } else {
+            self.out.write_all(COMMA)?;
+        }
+        self.out.write_all(DOUBLE_QUOTE)?;
+        self.out.write_all(name.as_bytes())?;
+        self.out.write_all(DOUBLE_QUOTE)?;
+        self.out.write_all(COLON)?;
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), CasErr> {
+        write_json(value, &mut self.out)
+    }
+
+    fn end_row(&mut self) -> Result<(), CasErr> {
+        self.out.write_all(RIGHT_BRACE)?;
+        Ok(())
+    }
+
+    fn end_result(&mut self) -> Result<(), CasErr> {
+        self.out.write_all(RIGHT_SQUARE)?;
+        self.out.write_all(NEW_LINE)?;
+        self.out.flush()?;
+        Ok(())
     }
-    let parser = find_serialiser(item_oid, &HashMap::new());
-    write_array_elements(&mut array, &counts, &parser, out)?;
-    Ok(())
 }
 
-fn write_array_elements<Out>(
-    bytes: &mut BinaryReader,
-    dimensions: &[i32],
-    serialiser: &Ser,
-    out: &mut Out,
-) -> Result<(), CasErr>
+/// Writes a decoded value as JSON.
+///
+/// JSON doesn't include a way to indicate NaN, Infinity or -Infinity because JSON numbers
+/// aren't tied to any particular implementation of number, floating point or otherwise.
+///
+/// serde_json serialises them to null, which makes sense, but for casql I would prefer to
+/// retain more information, so I have decided to write them as strings for now. Different
+/// languages will be able to parse different strings as floats, so this may cause issues.
+pub fn write_json<Out>(value: &Value, out: &mut Out) -> Result<(), CasErr>
 where
     Out: Write,
 {
-    if dimensions.len() == 1 {
-        let mut first = true;
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..dimensions[0] {
-            if first {
-                first = false
+    match value {
+        Value::Null => out.write_all(NULL)?,
+        Value::Bool(true) => out.write_all(TRUE)?,
+        Value::Bool(false) => out.write_all(FALSE)?,
+        Value::Int(int) => itoap::write(out, *int).map(|_| ())?,
+        Value::Float(float) => {
+            if float.is_finite() {
+                let mut ryu_buf = ryu::Buffer::new();
+                out.write_all(ryu_buf.format(*float).as_bytes())?;
             } else {
-                out.write(COMMA)?;
+                write_non_finite(float.is_nan(), float.is_sign_negative(), out)?;
             }
-            let size = bytes.i32();
-            if size == -1 {
-                out.write(NULL)?;
+        }
+        Value::Float32(float) => {
+            if float.is_finite() {
+                let mut ryu_buf = ryu::Buffer::new();
+                out.write_all(ryu_buf.format(*float).as_bytes())?;
             } else {
-                write_value(bytes.byte_slice(size as usize), serialiser, out)?;
+                write_non_finite(float.is_nan(), float.is_sign_negative(), out)?;
             }
         }
-        out.write(RIGHT_SQUARE)?;
-    } else {
-        let mut first = true;
-        out.write(LEFT_SQUARE)?;
-        for _ in 0..dimensions[0] {
-            if first {
-                first = false
-            } else {
-                out.write(COMMA)?;
+        Value::Numeric(num) if num == "NaN" => out.write_all(NAN)?,
+        Value::Numeric(num) => out.write_all(num.as_bytes())?,
+        Value::Text(text) => text::serialise_str(text.as_bytes(), out)?,
+        Value::Bytes(bytes) => {
+            out.write_all(LEFT_SQUARE)?;
+            for (idx, byte) in bytes.iter().enumerate() {
+                if idx > 0 {
+                    out.write_all(COMMA)?;
+                }
+                itoap::write(&mut *out, *byte)?;
+            }
+            out.write_all(RIGHT_SQUARE)?;
+        }
+        Value::BitString(text)
+        | Value::Date(text)
+        | Value::Time(text)
+        | Value::Timestamp(text)
+        | Value::Interval(text)
+        | Value::Uuid(text)
+        | Value::Inet(text)
+        | Value::MacAddr(text) => {
+            out.write_all(DOUBLE_QUOTE)?;
+            out.write_all(text.as_bytes())?;
+            out.write_all(DOUBLE_QUOTE)?;
+        }
+        Value::Json(json) | Value::Geometry(json) => out.write_all(json.as_bytes())?,
+        Value::Tid(block, offset) => write!(out, "[{},{}]", block, offset)?,
+        Value::Point(x, y) => write!(out, "[{},{}]", x, y)?,
+        Value::Points(points) => {
+            out.write_all(LEFT_SQUARE)?;
+            for (idx, (x, y)) in points.iter().enumerate() {
+                if idx > 0 {
+                    out.write_all(COMMA)?;
+                }
+                write!(out, "[{},{}]", x, y)?;
             }
-            write_array_elements(bytes, &dimensions[1..dimensions.len()], serialiser, out)?;
+            out.write_all(RIGHT_SQUARE)?;
         }
-        out.write(RIGHT_SQUARE)?;
+        Value::Circle(x, y, radius) => write!(out, "[[{},{}],{}]", x, y, radius)?,
+        Value::Line(a, b, c) => write!(out, "\"{}x + {}y + {} = 0\"", a, b, c)?,
+        Value::Array(elements) => {
+            out.write_all(LEFT_SQUARE)?;
+            for (idx, element) in elements.iter().enumerate() {
+                if idx > 0 {
+                    out.write_all(COMMA)?;
+                }
+                write_json(element, out)?;
+            }
+            out.write_all(RIGHT_SQUARE)?;
+        }
+        Value::Range {
+            lower,
+            upper,
+            lower_inc,
+            upper_inc,
+        } => {
+            out.write_all(r#"{"lower":"#.as_bytes())?;
+            write_json(lower, out)?;
+            out.write_all(r#","upper":"#.as_bytes())?;
+            write_json(upper, out)?;
+            write!(
+                out,
+                r#","lower_inc":{},"upper_inc":{}}}"#,
+                lower_inc, upper_inc
+            )?;
+        }
+        Value::EmptyRange => out.write_all(EMPTY_RANGE)?,
+        Value::Record(fields) => {
+            out.write_all(LEFT_BRACE)?;
+            for (idx, (name, value)) in fields.iter().enumerate() {
+                if idx > 0 {
+                    out.write_all(COMMA)?;
+                }
+                text::serialise_str(name.as_bytes(), out)?;
+                out.write_all(COLON)?;
+                write_json(value, out)?;
+            }
+            out.write_all(RIGHT_BRACE)?;
+        }
+        // The binary format, as a hex string like a bytea’s text format, e.g. "\\x0a2b".
+        Value::Unknown(bytes) => {
+            out.write_all("\"\\\\x".as_bytes())?;
+            for byte in bytes {
+                write!(out, "{:02x}", byte)?;
+            }
+            out.write_all(DOUBLE_QUOTE)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_non_finite<Out>(is_nan: bool, is_negative: bool, out: &mut Out) -> Result<(), CasErr>
+where
+    Out: Write,
+{
+    if is_nan {
+        out.write_all(NAN)?;
+    } else if is_negative {
+        out.write_all(NEGATIVE_INFINITY)?;
+    } else {
+        out.write_all(INFINITY)?;
     }
     Ok(())
 }
@@ -0,0 +1,418 @@
+use crate::cas_err::CasErr;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) const TEXT_FORMAT: i16 = 0;
+pub(crate) const BINARY_FORMAT: i16 = 1;
+
+/// A query parameter. Parameters can be sent as text, which Postgres parses as whatever type it
+/// infers for the parameter, or in the binary format of a particular type, with ToSql.
+///
+/// Strings convert into text parameters, so `vec!["1".into()]` works as well as
+/// `vec![Param::binary(&1i32)?]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub(crate) type_oid: i32,
+    pub(crate) format: i16,
+    pub(crate) value: Option<Vec<u8>>,
+}
+
+impl Param {
+    /// A parameter in the text format, with the type left for Postgres to infer.
+    pub fn text<S: Into<String>>(value: S) -> Self {
+        Param {
+            type_oid: 0,
+            format: TEXT_FORMAT,
+            value: Some(value.into().into_bytes()),
+        }
+    }
+
+    /// A parameter in the binary format of T’s Postgres type.
+    pub fn binary<T: ToSql + ?Sized>(value: &T) -> Result<Self, CasErr> {
+        let value = if value.is_null() {
+            None
+        } else {
+            let mut bytes = Vec::new();
+            value.to_sql(&mut bytes)?;
+            Some(bytes)
+        };
+        Ok(Param {
+            type_oid: T::OID,
+            format: BINARY_FORMAT,
+            value,
+        })
+    }
+
+    /// A null, with the type left for Postgres to infer.
+    pub fn null() -> Self {
+        Param {
+            type_oid: 0,
+            format: TEXT_FORMAT,
+            value: None,
+        }
+    }
+}
+
+impl From<String> for Param {
+    fn from(value: String) -> Self {
+        Param::text(value)
+    }
+}
+
+impl From<&str> for Param {
+    fn from(value: &str) -> Self {
+        Param::text(value)
+    }
+}
+
+/// A Rust type that can be written in the binary format of a Postgres type.
+// https://github.com/postgres/postgres/blob/master/src/include/catalog/pg_type.dat
+pub trait ToSql {
+    /// The oid of the Postgres type.
+    const OID: i32;
+    /// The oid of the array of the Postgres type, so that slices of the type can be sent as arrays.
+    const ARRAY_OID: i32;
+
+    /// Writes the value in the type’s binary format. It isn’t called for nulls.
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr>;
+
+    fn is_null(&self) -> bool {
+        false
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    const OID: i32 = T::OID;
+    const ARRAY_OID: i32 = T::ARRAY_OID;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        (*self).to_sql(out)
+    }
+
+    fn is_null(&self) -> bool {
+        (*self).is_null()
+    }
+}
+
+/// None is sent as a null.
+impl<T: ToSql> ToSql for Option<T> {
+    const OID: i32 = T::OID;
+    const ARRAY_OID: i32 = T::ARRAY_OID;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        match self {
+            Some(value) => value.to_sql(out),
+            None => Ok(()),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        match self {
+            Some(value) => value.is_null(),
+            None => true,
+        }
+    }
+}
+
+impl ToSql for bool {
+    const OID: i32 = 16;
+    const ARRAY_OID: i32 = 1000;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        out.push(*self as u8);
+        Ok(())
+    }
+}
+
+macro_rules! to_sql_be_bytes {
+    ($($rust_type:ty, $oid:expr, $array_oid:expr;)*) => {
+        $(
+            impl ToSql for $rust_type {
+                const OID: i32 = $oid;
+                const ARRAY_OID: i32 = $array_oid;
+
+                fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+                    out.extend_from_slice(&self.to_be_bytes());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+to_sql_be_bytes! {
+    i16, 21, 1005;
+    i32, 23, 1007;
+    i64, 20, 1016;
+    f32, 700, 1021;
+    f64, 701, 1022;
+}
+
+impl ToSql for str {
+    const OID: i32 = 25;
+    const ARRAY_OID: i32 = 1009;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        out.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
+}
+
+impl ToSql for String {
+    const OID: i32 = 25;
+    const ARRAY_OID: i32 = 1009;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        self.as_str().to_sql(out)
+    }
+}
+
+/// Bytes are sent as a bytea. u8 has no Postgres type, so Vec<u8> can’t be sent as an array like
+/// other Vecs, and isn’t a ToSql at all. Use a slice, or the Bytes wrapper, instead.
+impl ToSql for [u8] {
+    const OID: i32 = 17;
+    const ARRAY_OID: i32 = 1001;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        out.extend_from_slice(self);
+        Ok(())
+    }
+}
+
+/// A bytea.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl ToSql for Bytes {
+    const OID: i32 = 17;
+    const ARRAY_OID: i32 = 1001;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        self.0.as_slice().to_sql(out)
+    }
+}
+
+/// A uuid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uuid(pub [u8; 16]);
+
+impl Uuid {
+    /// Parses the hex digits of a uuid, with or without hyphens.
+    pub fn parse(uuid: &str) -> Result<Self, CasErr> {
+        let digits: Vec<u8> = uuid.bytes().filter(|byte| *byte != b'-').collect();
+        let invalid = || CasErr::ArgErr(format!("Invalid uuid: {}", uuid));
+        // from_str_radix would also accept a sign, so each digit is checked first.
+        if digits.len() != 32 || !digits.iter().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 16];
+        for (idx, pair) in digits.chunks(2).enumerate() {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            bytes[idx] = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Uuid(bytes))
+    }
+}
+
+impl ToSql for Uuid {
+    const OID: i32 = 2950;
+    const ARRAY_OID: i32 = 2951;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        out.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+/// A date, as the number of days after 2000-01-01, the same as Postgres.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date(pub i32);
+
+impl Date {
+    /// Returns None if the month or day don’t exist.
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<Self> {
+        let month_length = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            _ => return None,
+        };
+        if day == 0 || day > month_length {
+            return None;
+        }
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = if month <= 2 { year - 1 } else { year } as i64;
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let year_of_era = year - era * 400;
+        let month_from_march = (month as i64 + 9) % 12;
+        let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        // 730425 is the number of days from 0000-03-01 to 2000-01-01.
+        Some(Date((era * 146097 + day_of_era - 730425) as i32))
+    }
+}
+
+impl ToSql for Date {
+    const OID: i32 = 1082;
+    const ARRAY_OID: i32 = 1182;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        self.0.to_sql(out)
+    }
+}
+
+/// Sent as a timestamptz, in microseconds after 2000-01-01T00:00:00Z.
+impl ToSql for SystemTime {
+    const OID: i32 = 1184;
+    const ARRAY_OID: i32 = 1185;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        let unix_micros = match self.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_micros() as i64,
+            Err(before) => -(before.duration().as_micros() as i64),
+        };
+        (unix_micros - 946_684_800_000_000).to_sql(out)
+    }
+}
+
+/// JSON text, sent as a json.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<S: AsRef<str>>(pub S);
+
+impl<S: AsRef<str>> ToSql for Json<S> {
+    const OID: i32 = 114;
+    const ARRAY_OID: i32 = 199;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        self.0.as_ref().to_sql(out)
+    }
+}
+
+/// JSON text, sent as a jsonb.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jsonb<S: AsRef<str>>(pub S);
+
+impl<S: AsRef<str>> ToSql for Jsonb<S> {
+    const OID: i32 = 3802;
+    const ARRAY_OID: i32 = 3807;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        // jsonb version
+        out.push(1);
+        self.0.as_ref().to_sql(out)
+    }
+}
+
+/// Sent as an inet, for a single address.
+impl ToSql for IpAddr {
+    const OID: i32 = 869;
+    const ARRAY_OID: i32 = 1041;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        // family, netmask bits, is cidr, length
+        match self {
+            IpAddr::V4(addr) => {
+                out.extend_from_slice(&[2, 32, 0, 4]);
+                out.extend_from_slice(&addr.octets());
+            }
+            IpAddr::V6(addr) => {
+                out.extend_from_slice(&[3, 128, 0, 16]);
+                out.extend_from_slice(&addr.octets());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sent as a one-dimensional array.
+impl<T: ToSql> ToSql for [T] {
+    const OID: i32 = T::ARRAY_OID;
+    // Multi-dimensional arrays have the same type as one-dimensional ones, but they can’t be
+    // written from nested slices, because the inner slices may have different lengths.
+    const ARRAY_OID: i32 = 0;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        if Self::OID == 0 {
+            return Err(CasErr::ArgErr(
+                "Nested arrays can’t be sent as parameters.".to_owned(),
+            ));
+        }
+        let has_nulls = self.iter().any(|element| element.is_null());
+        let n_dims: i32 = if self.is_empty() { 0 } else { 1 };
+        out.extend_from_slice(&n_dims.to_be_bytes());
+        out.extend_from_slice(&(has_nulls as i32).to_be_bytes());
+        out.extend_from_slice(&T::OID.to_be_bytes());
+        if !self.is_empty() {
+            out.extend_from_slice(&(self.len() as i32).to_be_bytes());
+            // lower bound
+            out.extend_from_slice(&1i32.to_be_bytes());
+        }
+        for element in self {
+            if element.is_null() {
+                out.extend_from_slice(&(-1i32).to_be_bytes());
+            } else {
+                let mut bytes = Vec::new();
+                element.to_sql(&mut bytes)?;
+                out.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                out.extend_from_slice(&bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: ToSql> ToSql for Vec<T> {
+    const OID: i32 = T::ARRAY_OID;
+    const ARRAY_OID: i32 = 0;
+
+    fn to_sql(&self, out: &mut Vec<u8>) -> Result<(), CasErr> {
+        self.as_slice().to_sql(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_ymd() {
+        assert_eq!(Date::from_ymd(2000, 1, 1), Some(Date(0)));
+        assert_eq!(Date::from_ymd(2000, 3, 1), Some(Date(60)));
+        assert_eq!(Date::from_ymd(1999, 12, 31), Some(Date(-1)));
+        assert_eq!(Date::from_ymd(1970, 1, 1), Some(Date(-10957)));
+        assert_eq!(Date::from_ymd(2021, 2, 29), None);
+        assert_eq!(Date::from_ymd(2021, 13, 1), None);
+    }
+
+    #[test]
+    fn test_array() -> Result<(), CasErr> {
+        let param = Param::binary(&vec![Some(1i16), None])?;
+        assert_eq!(param.type_oid, 1005);
+        assert_eq!(
+            param.value.unwrap(),
+            vec![
+                0, 0, 0, 1, // dimensions
+                0, 0, 0, 1, // has nulls
+                0, 0, 0, 21, // element oid
+                0, 0, 0, 2, // length
+                0, 0, 0, 1, // lower bound
+                0, 0, 0, 2, 0, 1, // 1
+                255, 255, 255, 255, // null
+            ]
+        );
+        assert!(Param::binary(&vec![vec![1i16]]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuid() -> Result<(), CasErr> {
+        let uuid = Uuid::parse("27e31d5b-b544-44e0-83c1-379519b8a115")?;
+        assert_eq!(uuid.0[0], 0x27);
+        assert_eq!(uuid.0[15], 0x15);
+        assert!(Uuid::parse("27e31d5b").is_err());
+        assert!(Uuid::parse("+7e31d5b-b544-44e0-83c1-379519b8a115").is_err());
+        assert!(Uuid::parse("27e31d5b-b544-44e0-83c1-379519b8a11g").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use crate::args::ConnectionParams;
+use crate::cas_err::CasErr;
+use crate::postgres::connection::Conn;
+use crate::postgres::output::registry::Registry;
+use crate::postgres::output::sink::Sink;
+use crate::postgres::output::value::Rows;
+use crate::postgres::output::write::JsonSink;
+use crate::postgres::params::Param;
+use serde::de::DeserializeOwned;
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct PoolOptions {
+    /// The number of connections opened when the pool is created, and kept open when idle.
+    pub min_size: usize,
+    /// The most connections that can be open at once, including those checked out.
+    pub max_size: usize,
+    /// Idle connections beyond min_size are closed after this long.
+    pub idle_timeout: Option<Duration>,
+    /// Connections are closed after this long, even if they are in use, when they are returned.
+    pub max_lifetime: Option<Duration>,
+    /// How long Pool::get waits for a connection when max_size are already checked out. It is
+    /// also how long the health check, and the reset of a returned connection, may wait for the
+    /// server, before the connection is closed.
+    pub connection_timeout: Duration,
+    /// Whether to check idle connections with a cheap query before handing them out.
+    pub health_check: bool,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            min_size: 0,
+            max_size: 10,
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            connection_timeout: Duration::from_secs(30),
+            health_check: true,
+        }
+    }
+}
+
+/// A thread-safe pool of connections, all made with the same ConnectionParams. It can be cloned
+/// cheaply to share it between threads.
+///
+/// There is no background thread, so expired connections are only closed when a connection is
+/// checked out or returned.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    params: ConnectionParams,
+    options: PoolOptions,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+struct PoolState {
+    // The most recently returned connection is at the end, so it is handed out first, and the ones
+    // at the start are left to time out.
+    idle: Vec<IdleConn>,
+    // Including those checked out, and those being connected.
+    open: usize,
+}
+
+struct IdleConn {
+    conn: Conn,
+    created: Instant,
+    returned: Instant,
+}
+
+impl Pool {
+    /// Creates the pool, and opens min_size connections.
+    pub fn new(params: ConnectionParams, options: PoolOptions) -> Result<Self, CasErr> {
+        if options.max_size == 0 || options.min_size > options.max_size {
+            return Err(CasErr::ArgErr(format!(
+                "Invalid pool size: min_size is {}, max_size is {}",
+                options.min_size, options.max_size
+            )));
+        }
+        let mut idle = Vec::with_capacity(options.max_size);
+        for _ in 0..options.min_size {
+            let now = Instant::now();
+            idle.push(IdleConn {
+                conn: Conn::connect(params.clone())?,
+                created: now,
+                returned: now,
+            });
+        }
+        let open = idle.len();
+        Ok(Pool {
+            shared: Arc::new(Shared {
+                params,
+                options,
+                state: Mutex::new(PoolState { idle, open }),
+                returned: Condvar::new(),
+            }),
+        })
+    }
+
+    /// Checks out a connection, reusing an idle one if there is one, or opening a new one if there
+    /// are fewer than max_size. Otherwise it waits for one to be returned, for up to
+    /// connection_timeout.
+    pub fn get(&self) -> Result<PooledConn, CasErr> {
+        let deadline = Instant::now() + self.shared.options.connection_timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(idle) = state.idle.pop() {
+                if self.shared.is_expired(&idle, state.open) {
+                    state.open -= 1;
+                    continue;
+                }
+                drop(state);
+                let mut conn = idle.conn;
+                if !self.shared.options.health_check
+                    || self.shared.within_timeout(&mut conn, Conn::ping).is_ok()
+                {
+                    return Ok(PooledConn {
+                        conn: Some(conn),
+                        created: idle.created,
+                        broken: false,
+                        pool: self.shared.clone(),
+                    });
+                }
+                state = self.shared.lock();
+                state.open -= 1;
+                continue;
+            }
+            if state.open < self.shared.options.max_size {
+                state.open += 1;
+                drop(state);
+                return match Conn::connect(self.shared.params.clone()) {
+                    Ok(conn) => Ok(PooledConn {
+                        conn: Some(conn),
+                        created: Instant::now(),
+                        broken: false,
+                        pool: self.shared.clone(),
+                    }),
+                    Err(err) => {
+                        self.shared.discard();
+                        Err(err)
+                    }
+                };
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CasErr::IoErr(
+                    "timed out waiting for a connection from the pool".to_owned(),
+                ));
+            }
+            state = self
+                .shared
+                .returned
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// The number of open connections, including those checked out.
+    pub fn size(&self) -> usize {
+        self.shared.lock().open
+    }
+
+    /// The number of open connections that aren’t checked out.
+    pub fn idle(&self) -> usize {
+        self.shared.lock().idle.len()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // The state is never left half-updated, so it is still usable if another thread panicked.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_expired(&self, idle: &IdleConn, open: usize) -> bool {
+        let now = Instant::now();
+        let too_old = match self.options.max_lifetime {
+            Some(lifetime) => now - idle.created >= lifetime,
+            None => false,
+        };
+        let too_idle = match self.options.idle_timeout {
+            Some(timeout) => open > self.options.min_size && now - idle.returned >= timeout,
+            None => false,
+        };
+        too_old || too_idle
+    }
+
+    /// Forgets a connection that has been closed, letting another one be opened in its place.
+    /// Runs f with the connection_timeout applied to the connection’s reads and writes, so that a
+    /// server that has stopped responding can’t hang the thread. If it fails, the connection may
+    /// be partway through a response, and has to be closed.
+    fn within_timeout<F>(&self, conn: &mut Conn, f: F) -> Result<(), CasErr>
+    where
+        F: FnOnce(&mut Conn) -> Result<(), CasErr>,
+    {
+        // A zero timeout is rejected by the socket, so it means no timeout instead.
+        let timeout = Some(self.options.connection_timeout).filter(|timeout| !timeout.is_zero());
+        conn.set_timeout(timeout)?;
+        f(conn)?;
+        conn.set_timeout(None)
+    }
+
+    fn discard(&self) {
+        self.lock().open -= 1;
+        self.returned.notify_one();
+    }
+
+    fn put_back(&self, idle: IdleConn) {
+        let mut state = self.lock();
+        if self.is_expired(&idle, state.open) {
+            state.open -= 1;
+        } else {
+            state.idle.push(idle);
+        }
+        drop(state);
+        self.returned.notify_one();
+    }
+}
+
+/// A connection checked out of a Pool. It is returned to the pool when dropped, after its session
+/// is reset, unless an error showed the connection to be broken, in which case it is closed.
+pub struct PooledConn {
+    conn: Option<Conn>,
+    created: Instant,
+    broken: bool,
+    pool: Arc<Shared>,
+}
+
+impl PooledConn {
+    /// The same as Conn::query, but it keeps track of errors that leave the connection unusable, so
+    /// that it isn’t returned to the pool. After an error from the server, the rest of the response
+    /// has been read, and the connection can be reused, but after an IO error, or an error writing
+    /// the rows, the connection is either broken or part way through the response.
+    pub fn query<Out>(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+        out: &mut Out,
+    ) -> Result<(), CasErr>
+    where
+        Out: Write,
+    {
+        self.query_to(query, params, &mut JsonSink::new(out))
+    }
+
+    /// The same as Conn::query_to, but it keeps track of errors like PooledConn::query.
+    pub fn query_to<S>(
+        &mut self,
+        query: String,
+        params: Vec<Param>,
+        sink: &mut S,
+    ) -> Result<(), CasErr>
+    where
+        S: Sink + ?Sized,
+    {
+        let result = self.conn_mut().query_to(query, params, sink);
+        self.track(result)
+    }
+
+    /// The same as Conn::query_as, but it keeps track of errors like PooledConn::query.
+    pub fn query_as<T>(&mut self, query: String, params: Vec<Param>) -> Result<Vec<T>, CasErr>
+    where
+        T: DeserializeOwned,
+    {
+        let result = self.conn_mut().query_as(query, params);
+        self.track(result)
+    }
+
+    /// The same as Conn::query_rows. Errors reading the rows can’t be tracked, but the rest of the
+    /// response is skipped when the rows are dropped, and a connection that was broken is closed
+    /// when its session can’t be reset.
+    pub fn query_rows(&mut self, query: String, params: Vec<Param>) -> Result<Rows<'_>, CasErr> {
+        let conn = self
+            .conn
+            .as_mut()
+            .expect("The connection is only taken on drop.");
+        let result = conn.query_rows(query, params);
+        if let Err(err) = &result {
+            if !matches!(err, CasErr::ServerErr { .. }) {
+                self.broken = true;
+            }
+        }
+        result
+    }
+
+    /// The same as Conn::set_registry, but it keeps track of errors like PooledConn::query. The
+    /// registry stays with the connection when it is returned to the pool.
+    pub fn set_registry(&mut self, registry: Registry) -> Result<(), CasErr> {
+        let result = self.conn_mut().set_registry(registry);
+        self.track(result)
+    }
+
+    /// The parameters the server reported at startup, as Conn::server_param.
+    pub fn server_param(&self, name: &str) -> Option<&str> {
+        self.conn().server_param(name)
+    }
+
+    /// The server the connection was made to, as Conn::server.
+    pub fn server(&self) -> &str {
+        self.conn().server()
+    }
+
+    fn track<T>(&mut self, result: Result<T, CasErr>) -> Result<T, CasErr> {
+        match result {
+            Ok(_) | Err(CasErr::ServerErr { .. }) => {}
+            Err(_) => self.broken = true,
+        }
+        result
+    }
+
+    fn conn(&self) -> &Conn {
+        self.conn
+            .as_ref()
+            .expect("The connection is only taken on drop.")
+    }
+
+    fn conn_mut(&mut self) -> &mut Conn {
+        self.conn
+            .as_mut()
+            .expect("The connection is only taken on drop.")
+    }
+}
+
+impl Drop for PooledConn {
+    fn drop(&mut self) {
+        let mut conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => return,
+        };
+        if self.broken
+            || self
+                .pool
+                .within_timeout(&mut conn, Conn::reset_session)
+                .is_err()
+        {
+            self.pool.discard();
+            return;
+        }
+        self.pool.put_back(IdleConn {
+            conn,
+            created: self.created,
+            returned: Instant::now(),
+        });
+    }
+}
@@ -0,0 +1,872 @@
+use crate::args::{ConnectionParams, TargetSessionAttrs};
+use crate::cas_err::CasErr;
+use crate::postgres::backend_msgs;
+use crate::postgres::backend_msgs::{BackendMsg, Field, PgType};
+use crate::postgres::connection::{find_password, md5_password};
+use crate::postgres::frontend_msgs;
+use crate::postgres::output::registry::Registry;
+use crate::postgres::params::{Param, BINARY_FORMAT};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::io::ErrorKind;
+use std::mem;
+
+// The exchanges with the server are written here, without any reading or writing, so that the
+// sync and async connections share them. Each one says what to send, and what to make of each
+// message the server sends back, and the connections only move the bytes.
+
+// Look up the types that the registry doesn’t have serialisers for, and those it knows by name,
+// whose oids vary between databases, with the names and types of the attributes of composites.
+// Multiranges are only in Postgres 14 and later, so pg_range.rngmultitypid is read through
+// to_jsonb, which doesn’t fail on older servers that don’t have it.
+macro_rules! type_query {
+    ($from_where:literal) => {
+        concat!(
+            "SELECT t.oid, t.typname, t.typtype, t.typelem, t.typbasetype, t.typrelid, \
+             t.typnamespace, \
+             COALESCE((SELECT r.rngsubtype::int4 FROM pg_range r \
+                       WHERE r.rngtypid = t.oid \
+                       OR (to_jsonb(r) ->> 'rngmultitypid')::oid = t.oid), 0), \
+             ARRAY(SELECT a.attname::text FROM pg_attribute a \
+                   WHERE a.attrelid = t.typrelid AND a.attnum > 0 AND NOT a.attisdropped \
+                   ORDER BY a.attnum), \
+             ARRAY(SELECT a.atttypid::int4 FROM pg_attribute a \
+                   WHERE a.attrelid = t.typrelid AND a.attnum > 0 AND NOT a.attisdropped \
+                   ORDER BY a.attnum) \
+             FROM pg_type t ",
+            $from_where
+        )
+    };
+}
+const TYPE_QUERY_BY_OID: &str = type_query!("WHERE t.oid::int4 = ANY($1)");
+const TYPE_QUERY_BY_NAME: &str = type_query!("WHERE t.typname::text = ANY($1)");
+// Every type that isn’t built in, which is every type from 16384 (FirstNormalObjectId), except the
+// row types of tables and their arrays, of which there are too many to load up front.
+const TYPE_QUERY_USER_DEFINED: &str = type_query!(
+    "LEFT JOIN pg_type e ON e.oid = t.typelem \
+     LEFT JOIN pg_class c ON c.oid IN (t.typrelid, e.typrelid) \
+     WHERE t.oid::int8 >= 16384 AND (c.relkind IS NULL OR c.relkind = 'c')"
+);
+// The names the reg* values refer to, as the reg type, the oid and the name, from each of the
+// catalogs. They are qualified with their schemas, whether or not they are in the search path,
+// and the argument types of functions and operators are named as format_type names them.
+const REG_NAME_QUERY: &str = concat!(
+    "SELECT 24, p.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(p.proname) \
+     FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace WHERE p.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 2202, p.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(p.proname) \
+     || '(' || oidvectortypes(p.proargtypes) || ')' \
+     FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace WHERE p.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 2203, o.oid::int4, quote_ident(n.nspname) || '.' || o.oprname \
+     FROM pg_operator o JOIN pg_namespace n ON n.oid = o.oprnamespace \
+     WHERE o.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 2204, o.oid::int4, quote_ident(n.nspname) || '.' || o.oprname \
+     || '(' || CASE o.oprleft WHEN 0 THEN 'NONE' ELSE format_type(o.oprleft, NULL) END \
+     || ',' || CASE o.oprright WHEN 0 THEN 'NONE' ELSE format_type(o.oprright, NULL) END || ')' \
+     FROM pg_operator o JOIN pg_namespace n ON n.oid = o.oprnamespace \
+     WHERE o.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 2205, c.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(c.relname) \
+     FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace WHERE c.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 2206, t.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(t.typname) \
+     FROM pg_type t JOIN pg_namespace n ON n.oid = t.typnamespace WHERE t.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 3734, c.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(c.cfgname) \
+     FROM pg_ts_config c JOIN pg_namespace n ON n.oid = c.cfgnamespace \
+     WHERE c.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 3769, d.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(d.dictname) \
+     FROM pg_ts_dict d JOIN pg_namespace n ON n.oid = d.dictnamespace \
+     WHERE d.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 4089, n.oid::int4, quote_ident(n.nspname) \
+     FROM pg_namespace n WHERE n.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 4096, r.oid::int4, quote_ident(r.rolname) \
+     FROM pg_roles r WHERE r.oid::int4 = ANY($1) ",
+    "UNION ALL SELECT 4191, c.oid::int4, quote_ident(n.nspname) || '.' || quote_ident(c.collname) \
+     FROM pg_collation c JOIN pg_namespace n ON n.oid = c.collnamespace \
+     WHERE c.oid::int4 = ANY($1)"
+);
+// Money is cast to a numeric with the same number of fraction digits.
+const MONEY_FRACTION_DIGITS_QUERY: &str = "SELECT scale(0::money::numeric)::int4";
+
+/// The state of a connection that outlasts a single exchange.
+#[derive(Debug, Default)]
+pub(super) struct Session {
+    pub(super) registry: Registry,
+    pub(super) server_params: HashMap<String, String>,
+    // Whether any types have been looked up in pg_type.
+    pub(super) types_looked_up: bool,
+}
+
+/// What the connection has to do next.
+pub(super) enum Step<T> {
+    /// Write the messages, then read the response.
+    Send(Vec<u8>),
+    /// Read the next message.
+    Read,
+    /// The exchange is over.
+    Done(T),
+}
+
+impl<T> Step<T> {
+    /// Continues with f once a nested exchange is done.
+    fn then<U, F>(self, f: F) -> Result<Step<U>, CasErr>
+    where
+        F: FnOnce(T) -> Result<Step<U>, CasErr>,
+    {
+        match self {
+            Step::Send(msgs) => Ok(Step::Send(msgs)),
+            Step::Read => Ok(Step::Read),
+            Step::Done(output) => f(output),
+        }
+    }
+}
+
+/// An exchange of messages with the server. Errors from the server are only returned once the
+/// response has been read up to its ReadyForQuery, so that the connection can be used again.
+pub(super) trait Exchange {
+    type Output;
+
+    fn start(&mut self, session: &mut Session) -> Result<Step<Self::Output>, CasErr>;
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>)
+        -> Result<Step<Self::Output>, CasErr>;
+}
+
+/// Keeps the first error in a response, to be returned at its end.
+#[derive(Default)]
+struct Response {
+    error: Option<CasErr>,
+}
+
+impl Response {
+    fn fail(&mut self, msg: &[u8]) {
+        if self.error.is_none() {
+            let err_msg = backend_msgs::parse_error_response(msg);
+            self.error = Some(CasErr::from(err_msg));
+        }
+    }
+
+    fn end<T>(&mut self, output: T) -> Result<Step<T>, CasErr> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(Step::Done(output)),
+        }
+    }
+}
+
+/// Sends the startup message, and the password if the server asks for one, then keeps the
+/// parameters the server reports, until it is ready for queries.
+pub(super) struct Startup<'a> {
+    params: &'a ConnectionParams,
+    host: &'a str,
+    port: u16,
+}
+
+impl<'a> Startup<'a> {
+    pub(super) fn new(params: &'a ConnectionParams, host: &'a str, port: u16) -> Self {
+        Startup { params, host, port }
+    }
+}
+
+impl<'a> Exchange for Startup<'a> {
+    type Output = ();
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<()>, CasErr> {
+        let dbname = self.params.database.as_ref().unwrap_or(&self.params.user);
+        let settings: &[(&str, &str)] = if self.params.read_only {
+            &[("default_transaction_read_only", "on")]
+        } else {
+            &[]
+        };
+        let msg = frontend_msgs::startup_msg(&self.params.user, dbname, settings, 3, 0);
+        Ok(Step::Send(msg))
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<()>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            BackendMsg::AuthenticationCleartextPassword => {
+                let password = find_password(self.params, self.host, self.port)?;
+                Ok(Step::Send(frontend_msgs::password_msg(&password)))
+            }
+            BackendMsg::AuthenticationMD5Password => {
+                let salt = [msg[9], msg[10], msg[11], msg[12]];
+                let password = find_password(self.params, self.host, self.port)?;
+                let hashed_pass = md5_password(&self.params.user, &password, salt);
+                Ok(Step::Send(frontend_msgs::password_msg(&hashed_pass)))
+            }
+            BackendMsg::ErrorResponse => {
+                let err_msg = backend_msgs::parse_error_response(&msg);
+                Err(CasErr::from(err_msg))
+            }
+            BackendMsg::AuthenticationOk => Ok(Step::Read),
+            BackendMsg::ParameterStatus => {
+                let (name, value) = backend_msgs::parse_parameter_status(&msg);
+                session.server_params.insert(name, value);
+                Ok(Step::Read)
+            }
+            BackendMsg::BackendKeyData => Ok(Step::Read),
+            BackendMsg::ReadyForQuery => Ok(Step::Done(())),
+            _ => Err(CasErr::PostgresErr(format!(
+                "Received unexpected message from Postgres: {:?}",
+                msg
+            ))),
+        }
+    }
+}
+
+/// Runs a simple query and returns the first value of the first row, as text.
+pub(super) struct SimpleQuery<'a> {
+    query: &'a str,
+    value: Option<String>,
+    response: Response,
+}
+
+impl<'a> SimpleQuery<'a> {
+    pub(super) fn new(query: &'a str) -> Self {
+        SimpleQuery {
+            query,
+            value: None,
+            response: Response::default(),
+        }
+    }
+}
+
+impl<'a> Exchange for SimpleQuery<'a> {
+    type Output = Option<String>;
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<Option<String>>, CasErr> {
+        Ok(Step::Send(frontend_msgs::query_msg(self.query)))
+    }
+
+    fn handle(
+        &mut self,
+        _session: &mut Session,
+        msg: Vec<u8>,
+    ) -> Result<Step<Option<String>>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            BackendMsg::ErrorResponse => self.response.fail(&msg),
+            BackendMsg::DataRow if self.value.is_none() => {
+                self.value = backend_msgs::parse_text_row(&msg)
+                    .into_iter()
+                    .next()
+                    .flatten();
+            }
+            BackendMsg::ReadyForQuery => return self.response.end(self.value.take()),
+            _ => {}
+        }
+        Ok(Step::Read)
+    }
+}
+
+/// Finds the reason the server doesn’t match the target_session_attrs, if it doesn’t. Since
+/// Postgres 14, the server reports in_hot_standby and default_transaction_read_only at startup,
+/// but older servers have to be asked.
+pub(super) struct CheckSessionAttrs {
+    attrs: TargetSessionAttrs,
+    query: Option<(SimpleQuery<'static>, &'static str)>,
+}
+
+impl CheckSessionAttrs {
+    pub(super) fn new(attrs: TargetSessionAttrs) -> Self {
+        CheckSessionAttrs { attrs, query: None }
+    }
+
+    fn reason(&self, flag: bool) -> Option<&'static str> {
+        match self.attrs {
+            TargetSessionAttrs::ReadWrite if flag => Some("session is read-only"),
+            TargetSessionAttrs::ReadOnly if !flag => Some("session is not read-only"),
+            TargetSessionAttrs::Primary if flag => Some("server is in hot standby mode"),
+            TargetSessionAttrs::Standby if !flag => Some("server is not in hot standby mode"),
+            _ => None,
+        }
+    }
+}
+
+impl Exchange for CheckSessionAttrs {
+    type Output = Option<&'static str>;
+
+    fn start(&mut self, session: &mut Session) -> Result<Step<Option<&'static str>>, CasErr> {
+        let param = |name: &str| session.server_params.get(name).map(|value| value == "on");
+        let (reported, query, on) = match self.attrs {
+            TargetSessionAttrs::Any => return Ok(Step::Done(None)),
+            TargetSessionAttrs::ReadWrite | TargetSessionAttrs::ReadOnly => {
+                let reported = match (
+                    param("default_transaction_read_only"),
+                    param("in_hot_standby"),
+                ) {
+                    (Some(read_only), Some(hot_standby)) => Some(read_only || hot_standby),
+                    _ => None,
+                };
+                (reported, "SHOW transaction_read_only", "on")
+            }
+            TargetSessionAttrs::Primary | TargetSessionAttrs::Standby => (
+                param("in_hot_standby"),
+                "SELECT pg_catalog.pg_is_in_recovery()",
+                "t",
+            ),
+        };
+        match reported {
+            Some(flag) => Ok(Step::Done(self.reason(flag))),
+            None => {
+                let mut query = SimpleQuery::new(query);
+                let step = query.start(session)?;
+                self.query = Some((query, on));
+                step.then(|value| Ok(Step::Done(self.reason(value.as_deref() == Some(on)))))
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        session: &mut Session,
+        msg: Vec<u8>,
+    ) -> Result<Step<Option<&'static str>>, CasErr> {
+        let (query, on) = self.query.as_mut().expect("The query was sent.");
+        let on = *on;
+        query
+            .handle(session, msg)?
+            .then(|value| Ok(Step::Done(self.reason(value.as_deref() == Some(on)))))
+    }
+}
+
+/// Runs one of the pg_type queries, and adds the types to the registry, then looks up the types of
+/// their elements and bases, until every type they refer to is known. Its columns all have
+/// built-in serialisers, so it doesn’t need to be described before it is bound.
+pub(super) struct TypeLookup {
+    query: &'static str,
+    params: Vec<Param>,
+    types: Vec<PgType>,
+    response: Response,
+}
+
+impl TypeLookup {
+    fn new(query: &'static str, params: Vec<Param>) -> Self {
+        TypeLookup {
+            query,
+            params,
+            types: Vec::new(),
+            response: Response::default(),
+        }
+    }
+
+    /// Looks up the types with these oids.
+    pub(super) fn by_oid(oids: &[i32]) -> Result<Self, CasErr> {
+        Ok(TypeLookup::new(
+            TYPE_QUERY_BY_OID,
+            vec![Param::binary(oids)?],
+        ))
+    }
+
+    /// Looks up the oids of the types the registry knows by name.
+    pub(super) fn by_name(registry: &Registry) -> Result<Self, CasErr> {
+        let names = Param::binary(&registry.names())?;
+        Ok(TypeLookup::new(TYPE_QUERY_BY_NAME, vec![names]))
+    }
+
+    /// Looks up every type that isn’t built in, other than the row types of tables.
+    pub(super) fn user_defined() -> Self {
+        TypeLookup::new(TYPE_QUERY_USER_DEFINED, Vec::new())
+    }
+}
+
+impl Exchange for TypeLookup {
+    type Output = ();
+
+    fn start(&mut self, session: &mut Session) -> Result<Step<()>, CasErr> {
+        session.types_looked_up = true;
+        Ok(Step::Send(extended_query(self.query, &self.params)))
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<()>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            BackendMsg::ErrorResponse => self.response.fail(&msg),
+            BackendMsg::DataRow => self.types.push(backend_msgs::parse_type_row(&msg)),
+            BackendMsg::ReadyForQuery => {
+                self.response.end(())?;
+                let unresolved = session.registry.add_types(mem::take(&mut self.types));
+                if unresolved.is_empty() {
+                    return Ok(Step::Done(()));
+                }
+                *self = TypeLookup::by_oid(&unresolved)?;
+                return self.start(session);
+            }
+            _ => {}
+        }
+        Ok(Step::Read)
+    }
+}
+
+/// Asks the server how many fraction digits money values have, which depends on its lc_monetary.
+/// It is only looked up once, so a later SET lc_monetary isn’t noticed.
+#[derive(Default)]
+struct MoneyFractionDigits {
+    response: Response,
+}
+
+impl Exchange for MoneyFractionDigits {
+    type Output = ();
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<()>, CasErr> {
+        Ok(Step::Send(extended_query(MONEY_FRACTION_DIGITS_QUERY, &[])))
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<()>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            BackendMsg::ErrorResponse => self.response.fail(&msg),
+            BackendMsg::DataRow => {
+                let digits = backend_msgs::parse_int4_row(&msg);
+                session.registry.set_money_fraction_digits(digits as u32);
+            }
+            BackendMsg::ReadyForQuery => return self.response.end(()),
+            _ => {}
+        }
+        Ok(Step::Read)
+    }
+}
+
+// A query is sent in two parts. The statement is parsed and described first, so that the format of
+// each result column can be chosen from its type, and the messages arrive in the following order:
+// ParseComplete
+// ParameterDescription
+// RowDescription, or NoData if the statement doesn’t return rows
+// ReadyForQuery
+//
+// Then the statement is bound and executed, after being parsed again if the types of its columns
+// had to be looked up:
+// ParseComplete, if it was parsed again
+// BindComplete
+// DataRow...
+// Close
+// ReadyForQuery
+//
+// An ErrorResponse may replace any of these after ParseComplete, and is then followed by
+// ReadyForQuery. The messages are always read up to the ReadyForQuery, so that the connection can
+// be used again after an error.
+
+/// Parses and describes the query, looks up the types of its columns that aren’t known, then binds
+/// and executes it, with each column requested in the format its serialiser needs. It is done at
+/// the BindComplete, and returns the fields, for reading the rows that follow.
+pub(super) struct Query<'a> {
+    query: &'a str,
+    params: &'a [Param],
+    fields: Vec<Field>,
+    state: QueryState,
+    // Looking up the types replaces the unnamed statement, so it has to be parsed again.
+    replaced_statement: bool,
+    response: Response,
+}
+
+enum QueryState {
+    Describing,
+    LookingUpTypes(TypeLookup),
+    LookingUpMoney(MoneyFractionDigits),
+    Binding,
+}
+
+impl<'a> Query<'a> {
+    pub(super) fn new(query: &'a str, params: &'a [Param]) -> Self {
+        Query {
+            query,
+            params,
+            fields: Vec::new(),
+            state: QueryState::Describing,
+            replaced_statement: false,
+            response: Response::default(),
+        }
+    }
+
+    fn look_up_types(&mut self, session: &mut Session) -> Result<Step<Vec<Field>>, CasErr> {
+        let unresolved = session.registry.field_oids_to_look_up(&self.fields);
+        if unresolved.is_empty() {
+            return self.look_up_money(session);
+        }
+        let mut lookup = TypeLookup::by_oid(&unresolved)?;
+        let step = lookup.start(session)?;
+        self.state = QueryState::LookingUpTypes(lookup);
+        self.replaced_statement = true;
+        step.then(|()| self.look_up_money(session))
+    }
+
+    fn look_up_money(&mut self, session: &mut Session) -> Result<Step<Vec<Field>>, CasErr> {
+        if !session.registry.needs_money_fraction_digits(&self.fields) {
+            return Ok(self.bind(session));
+        }
+        let mut lookup = MoneyFractionDigits::default();
+        let step = lookup.start(session)?;
+        self.state = QueryState::LookingUpMoney(lookup);
+        self.replaced_statement = true;
+        step.then(|()| Ok(self.bind(session)))
+    }
+
+    fn bind(&mut self, session: &mut Session) -> Step<Vec<Field>> {
+        let mut msgs = Vec::new();
+        if self.replaced_statement {
+            msgs.extend(frontend_msgs::parse_msg(self.query, self.params));
+        }
+        let formats = session.registry.result_formats(&self.fields);
+        for (field, format) in self.fields.iter_mut().zip(&formats) {
+            field.format = *format;
+        }
+        msgs.extend(frontend_msgs::bind_msg(self.params, &formats));
+        msgs.extend(frontend_msgs::execute_msg());
+        msgs.extend(frontend_msgs::sync_msg());
+        self.state = QueryState::Binding;
+        Step::Send(msgs)
+    }
+}
+
+impl<'a> Exchange for Query<'a> {
+    type Output = Vec<Field>;
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<Vec<Field>>, CasErr> {
+        let mut msgs = frontend_msgs::parse_msg(self.query, self.params);
+        msgs.extend(frontend_msgs::describe_msg());
+        msgs.extend(frontend_msgs::sync_msg());
+        Ok(Step::Send(msgs))
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<Vec<Field>>, CasErr> {
+        match &mut self.state {
+            QueryState::Describing => match backend_msgs::type_of(&msg) {
+                BackendMsg::ErrorResponse => self.response.fail(&msg),
+                BackendMsg::ParseComplete => {}
+                BackendMsg::ParameterDescription => {}
+                BackendMsg::RowDescription => self.fields = backend_msgs::parse_row_desc(&msg),
+                BackendMsg::NoData => {}
+                BackendMsg::ReadyForQuery => {
+                    self.response.end(())?;
+                    return self.look_up_types(session);
+                }
+                _ => eprintln!("Received unexpected message from Postgres: {:?}", msg),
+            },
+            QueryState::LookingUpTypes(lookup) => {
+                let step = lookup.handle(session, msg)?;
+                return step.then(|()| self.look_up_money(session));
+            }
+            QueryState::LookingUpMoney(lookup) => {
+                let step = lookup.handle(session, msg)?;
+                return step.then(|()| Ok(self.bind(session)));
+            }
+            QueryState::Binding => match backend_msgs::type_of(&msg) {
+                BackendMsg::ErrorResponse => self.response.fail(&msg),
+                // If the statement was parsed again, after its types were looked up.
+                BackendMsg::ParseComplete => {}
+                // This should come just before the first data row.
+                BackendMsg::BindComplete => return Ok(Step::Done(mem::take(&mut self.fields))),
+                BackendMsg::ReadyForQuery => return self.response.end(Vec::new()),
+                _ => eprintln!("Received unexpected message from Postgres: {:?}", msg),
+            },
+        }
+        Ok(Step::Read)
+    }
+}
+
+/// Reads the rest of a response into memory, then looks up what is needed to decode its rows,
+/// since the connection can’t be used for that until the response has been read: the field types
+/// of anonymous records, which are only known from the rows, the names of reg* values, and the
+/// text of record fields whose types casql can’t decode. Returns the messages, for reading the
+/// rows from.
+pub(super) struct BufferRows<'a> {
+    fields: &'a [Field],
+    msgs: VecDeque<Vec<u8>>,
+    state: BufferState,
+    // The types that have been looked up for the rows, which aren’t asked for again if the server
+    // doesn’t have them.
+    looked_up: HashSet<i32>,
+    // The values to convert to text, once the reg* names are known.
+    output_values: Vec<(i32, Vec<u8>)>,
+}
+
+enum BufferState {
+    Reading,
+    LookingUpTypes(TypeLookup),
+    LookingUpRegNames(RegNameLookup),
+    ConvertingToText(OutputTextLookup),
+}
+
+impl<'a> BufferRows<'a> {
+    pub(super) fn new(fields: &'a [Field]) -> Self {
+        BufferRows {
+            fields,
+            msgs: VecDeque::new(),
+            state: BufferState::Reading,
+            looked_up: HashSet::new(),
+            output_values: Vec::new(),
+        }
+    }
+
+    // The records and composites found by a lookup may hold more types that need to be, so the
+    // rows are visited again after each one, and the reg* values are only named, and the values
+    // without a serialiser converted, once they are all known.
+    fn look_up(&mut self, session: &mut Session) -> Result<Step<VecDeque<Vec<u8>>>, CasErr> {
+        let rows = self
+            .msgs
+            .iter()
+            .filter(|msg| matches!(backend_msgs::type_of(msg), BackendMsg::DataRow));
+        let mut oids = Vec::new();
+        let mut reg_oids = Vec::new();
+        let mut output_values = HashSet::new();
+        for row in rows {
+            session
+                .registry
+                .visit_row(self.fields, row, &mut |oid, bytes| {
+                    oids.push(oid);
+                    if session.registry.names_reg_type(oid) {
+                        reg_oids.push(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+                    } else if session.registry.needs_output_text(oid) {
+                        output_values.insert((oid, bytes.to_vec()));
+                    }
+                });
+        }
+        let unresolved: Vec<i32> = session
+            .registry
+            .unresolved_oids(oids)
+            .into_iter()
+            .filter(|oid| self.looked_up.insert(*oid))
+            .collect();
+        if !unresolved.is_empty() {
+            let mut lookup = TypeLookup::by_oid(&unresolved)?;
+            let step = lookup.start(session)?;
+            self.state = BufferState::LookingUpTypes(lookup);
+            return step.then(|()| self.look_up(session));
+        }
+        self.output_values = output_values.into_iter().collect();
+        if !reg_oids.is_empty() {
+            reg_oids.sort_unstable();
+            reg_oids.dedup();
+            let mut lookup = RegNameLookup::new(&reg_oids)?;
+            let step = lookup.start(session)?;
+            self.state = BufferState::LookingUpRegNames(lookup);
+            return step.then(|()| self.convert_to_text(session));
+        }
+        self.convert_to_text(session)
+    }
+
+    fn convert_to_text(
+        &mut self,
+        session: &mut Session,
+    ) -> Result<Step<VecDeque<Vec<u8>>>, CasErr> {
+        if self.output_values.is_empty() {
+            return Ok(Step::Done(mem::take(&mut self.msgs)));
+        }
+        let mut lookup = OutputTextLookup::new(mem::take(&mut self.output_values));
+        let step = lookup.start(session)?;
+        self.state = BufferState::ConvertingToText(lookup);
+        step.then(|()| Ok(Step::Done(mem::take(&mut self.msgs))))
+    }
+}
+
+impl<'a> Exchange for BufferRows<'a> {
+    type Output = VecDeque<Vec<u8>>;
+
+    fn start(&mut self, session: &mut Session) -> Result<Step<VecDeque<Vec<u8>>>, CasErr> {
+        session.registry.clear_row_lookups();
+        Ok(Step::Read)
+    }
+
+    fn handle(
+        &mut self,
+        session: &mut Session,
+        msg: Vec<u8>,
+    ) -> Result<Step<VecDeque<Vec<u8>>>, CasErr> {
+        match &mut self.state {
+            BufferState::Reading => {
+                let ready = matches!(backend_msgs::type_of(&msg), BackendMsg::ReadyForQuery);
+                self.msgs.push_back(msg);
+                if ready {
+                    return self.look_up(session);
+                }
+                Ok(Step::Read)
+            }
+            BufferState::LookingUpTypes(lookup) => {
+                let step = lookup.handle(session, msg)?;
+                step.then(|()| self.look_up(session))
+            }
+            BufferState::LookingUpRegNames(lookup) => {
+                let step = lookup.handle(session, msg)?;
+                step.then(|()| self.convert_to_text(session))
+            }
+            BufferState::ConvertingToText(lookup) => {
+                let step = lookup.handle(session, msg)?;
+                step.then(|()| Ok(Step::Done(mem::take(&mut self.msgs))))
+            }
+        }
+    }
+}
+
+/// Looks up the names the reg* values refer to, in one query over the catalogs, for the registry
+/// to decode them with.
+struct RegNameLookup {
+    params: Vec<Param>,
+    names: Vec<(i32, u32, String)>,
+    response: Response,
+}
+
+impl RegNameLookup {
+    fn new(oids: &[i32]) -> Result<Self, CasErr> {
+        Ok(RegNameLookup {
+            params: vec![Param::binary(oids)?],
+            names: Vec::new(),
+            response: Response::default(),
+        })
+    }
+}
+
+impl Exchange for RegNameLookup {
+    type Output = ();
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<()>, CasErr> {
+        Ok(Step::Send(extended_query(REG_NAME_QUERY, &self.params)))
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<()>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            BackendMsg::ErrorResponse => self.response.fail(&msg),
+            BackendMsg::DataRow => self.names.push(backend_msgs::parse_reg_name_row(&msg)),
+            BackendMsg::ReadyForQuery => {
+                let step = self.response.end(())?;
+                session.registry.set_reg_names(mem::take(&mut self.names));
+                return Ok(step);
+            }
+            _ => {}
+        }
+        Ok(Step::Read)
+    }
+}
+
+// Each value is a column of the query, which can have at most 1664.
+const OUTPUT_TEXT_BATCH: usize = 1000;
+
+/// Converts the values of record fields whose types casql can’t decode to text, by sending them
+/// back to the server as parameters of their types, to be cast to text with the types’ output
+/// functions. Values in a batch the server can’t convert are left as they are.
+struct OutputTextLookup {
+    values: Vec<(i32, Vec<u8>)>,
+    converted: usize,
+    texts: Vec<Option<String>>,
+    failed: bool,
+}
+
+impl OutputTextLookup {
+    fn new(values: Vec<(i32, Vec<u8>)>) -> Self {
+        OutputTextLookup {
+            values,
+            converted: 0,
+            texts: Vec::new(),
+            failed: false,
+        }
+    }
+
+    fn batch(&self) -> &[(i32, Vec<u8>)] {
+        let end = (self.converted + OUTPUT_TEXT_BATCH).min(self.values.len());
+        &self.values[self.converted..end]
+    }
+
+    fn send_batch(&self) -> Step<()> {
+        let batch = self.batch();
+        let columns: Vec<String> = (1..=batch.len())
+            .map(|idx| format!("${}::text", idx))
+            .collect();
+        let params: Vec<Param> = batch
+            .iter()
+            .map(|(oid, bytes)| Param {
+                type_oid: *oid,
+                format: BINARY_FORMAT,
+                value: Some(bytes.clone()),
+            })
+            .collect();
+        Step::Send(extended_query(
+            &format!("SELECT {}", columns.join(", ")),
+            &params,
+        ))
+    }
+}
+
+impl Exchange for OutputTextLookup {
+    type Output = ();
+
+    fn start(&mut self, _session: &mut Session) -> Result<Step<()>, CasErr> {
+        Ok(self.send_batch())
+    }
+
+    fn handle(&mut self, session: &mut Session, msg: Vec<u8>) -> Result<Step<()>, CasErr> {
+        match backend_msgs::type_of(&msg) {
+            // The type may not have a binary input function, though it has an output one.
+            BackendMsg::ErrorResponse => self.failed = true,
+            BackendMsg::DataRow => self.texts = backend_msgs::parse_text_row(&msg),
+            BackendMsg::ReadyForQuery => {
+                let texts = mem::take(&mut self.texts);
+                if !self.failed {
+                    let converted = self
+                        .batch()
+                        .iter()
+                        .cloned()
+                        .zip(texts)
+                        .filter_map(|((oid, bytes), text)| text.map(|text| (oid, bytes, text)));
+                    session.registry.add_output_texts(converted);
+                }
+                self.failed = false;
+                self.converted += self.batch().len();
+                if self.converted < self.values.len() {
+                    return Ok(self.send_batch());
+                }
+                return Ok(Step::Done(()));
+            }
+            _ => {}
+        }
+        Ok(Step::Read)
+    }
+}
+
+/// What a message among the rows of a response means.
+pub(super) enum RowMsg {
+    Row(Vec<u8>),
+    /// The query failed part way through, and the rest of the response has to be skipped.
+    Error(CasErr),
+    /// The ReadyForQuery at the end of the response.
+    End,
+    Other,
+}
+
+pub(super) fn row_msg(msg: Vec<u8>) -> RowMsg {
+    match backend_msgs::type_of(&msg) {
+        BackendMsg::DataRow => RowMsg::Row(msg),
+        BackendMsg::Close => RowMsg::Other,
+        BackendMsg::ReadyForQuery => RowMsg::End,
+        BackendMsg::ErrorResponse => {
+            let err_msg = backend_msgs::parse_error_response(&msg);
+            RowMsg::Error(CasErr::from(err_msg))
+        }
+        _ => {
+            eprintln!("Received unexpected message from Postgres: {:?}", msg);
+            RowMsg::Other
+        }
+    }
+}
+
+/// The length of a message, from its five byte header, which is a one byte tag, and a four byte
+/// length that includes itself but not the tag.
+pub(super) fn msg_len(header: &[u8]) -> usize {
+    1 + i32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize
+}
+
+/// Parses, binds and executes a query whose result columns are all requested in binary.
+fn extended_query(query: &str, params: &[Param]) -> Vec<u8> {
+    let mut msgs = frontend_msgs::parse_msg(query, params);
+    msgs.extend(frontend_msgs::bind_msg(params, &[BINARY_FORMAT]));
+    msgs.extend(frontend_msgs::execute_msg());
+    msgs.extend(frontend_msgs::sync_msg());
+    msgs
+}
+
+/// The error for a failed read or write on the connection. If the server closed or reset it, the
+/// error is transient, unlike IO errors from elsewhere, such as writing the output.
+pub(super) fn socket_err(err: io::Error) -> CasErr {
+    match err.kind() {
+        ErrorKind::UnexpectedEof => {
+            CasErr::IoConnLost("connection to the database was closed unexpectedly".to_owned())
+        }
+        ErrorKind::BrokenPipe
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected => CasErr::IoConnLost(err.to_string()),
+        _ => CasErr::from(err),
+    }
+}
@@ -1,62 +1,83 @@
 use crate::cas_err::CasErr;
 use crate::postgres::backend_msgs;
 use crate::postgres::backend_msgs::{BackendMsg, Field};
-use crate::postgres::msg_iter::MsgIter;
+use crate::postgres::protocol;
+use crate::postgres::protocol::RowMsg;
 
-// The messages arrive from Postgres in the following order:
-// ParseComplete
-// ParameterDescription
-// RowDescription
-// BindComplete
-// DataRow...
-// Close
-// ReadyForQuery
-pub struct RowIter<'msgs> {
-    msgs: &'msgs mut MsgIter<'msgs>,
+pub struct RowIter<'stream> {
+    // The messages are read from the connection, or from memory if the response had to be read
+    // before the rows could be decoded.
+    msgs: Box<dyn Iterator<Item = Vec<u8>> + 'stream>,
     pub fields: Vec<Field>,
+    finished: bool,
 }
 
-impl<'msgs> RowIter<'msgs> {
-    pub fn from(msgs: &'msgs mut MsgIter<'msgs>) -> Result<Self, CasErr> {
-        let mut fields = Vec::new();
-        while let Some(msg) = msgs.next() {
-            match backend_msgs::type_of(&msg) {
-                BackendMsg::ErrorResponse => {
-                    let err_msg = backend_msgs::parse_error_response(&msg);
-                    Err(CasErr::PostgresErr(err_msg.to_string()))?;
-                }
-                BackendMsg::ParseComplete => {}
-                BackendMsg::ParameterDescription => {}
-                BackendMsg::RowDescription => {
-                    fields = backend_msgs::parse_row_desc(&msg);
-                }
-                BackendMsg::BindComplete => {
-                    // This should come just before the first data row.
-                    break;
-                }
-                _ => {
-                    eprintln!("Received unexpected message from Postgres: {:?}", msg);
-                }
-            }
+impl<'stream> RowIter<'stream> {
+    /// The rows that follow the BindComplete. The fields are those from the description, with the
+    /// formats that were requested for them.
+    pub fn new<I>(msgs: I, fields: Vec<Field>) -> Self
+    where
+        I: Iterator<Item = Vec<u8>> + 'stream,
+    {
+        RowIter {
+            msgs: Box::new(msgs),
+            fields,
+            finished: false,
         }
-        Ok(RowIter { msgs, fields })
     }
 }
 
-impl<'msgs> Iterator for RowIter<'msgs> {
-    type Item = Vec<u8>;
+impl<'stream> Iterator for RowIter<'stream> {
+    type Item = Result<Vec<u8>, CasErr>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.msgs
-            .next()
-            .and_then(|msg| match backend_msgs::type_of(&msg) {
-                BackendMsg::DataRow => Some(msg),
-                BackendMsg::Close => self.next(),
-                BackendMsg::ReadyForQuery => None, // finished
-                _ => {
-                    eprintln!("Received unexpected message from Postgres: {:?}", msg);
+        if self.finished {
+            return None;
+        }
+        match self.msgs.next() {
+            Some(msg) => match protocol::row_msg(msg) {
+                RowMsg::Row(msg) => Some(Ok(msg)),
+                RowMsg::End => {
+                    // finished
+                    self.finished = true;
                     None
                 }
-            })
+                RowMsg::Error(err) => {
+                    skip_to_ready(&mut self.msgs);
+                    self.finished = true;
+                    Some(Err(err))
+                }
+                RowMsg::Other => self.next(),
+            },
+            None => {
+                // The connection was closed before the query finished.
+                self.finished = true;
+                Some(Err(CasErr::IoConnLost(
+                    "connection to the database was closed unexpectedly".to_owned(),
+                )))
+            }
+        }
+    }
+}
+
+/// If the rows aren’t all read, because they were dropped early or an error was returned while
+/// decoding or writing them, the rest of the response is skipped, so that the connection can be
+/// used again.
+impl<'stream> Drop for RowIter<'stream> {
+    fn drop(&mut self) {
+        if !self.finished {
+            skip_to_ready(&mut self.msgs);
+        }
+    }
+}
+
+fn skip_to_ready<I>(msgs: &mut I)
+where
+    I: Iterator<Item = Vec<u8>> + ?Sized,
+{
+    for msg in msgs {
+        if let BackendMsg::ReadyForQuery = backend_msgs::type_of(&msg) {
+            break;
+        }
     }
 }
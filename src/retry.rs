@@ -0,0 +1,156 @@
+use crate::cas_err::CasErr;
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+// Output up to this size is held back from stdout, so that a failed query can be retried without
+// any partial JSON having been written. Beyond it, the output is streamed, and the query can no
+// longer be retried.
+const HELD_OUTPUT_LIMIT: usize = 8 * 1024 * 1024;
+
+/// How many times to retry after a transient error, see CasErr::is_transient, and how long to wait
+/// before the first retry. The wait doubles after each retry.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Calls f until it succeeds, fails with an error that isn’t transient, or runs out of retries.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T, CasErr>
+    where
+        F: FnMut() -> Result<T, CasErr>,
+    {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(err) if err.is_transient() && attempt < self.retries => {
+                    self.wait(attempt, &err);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Waits before the given retry, where 0 is the first.
+    pub fn wait(&self, attempt: u32, err: &CasErr) {
+        let delay = self.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        eprintln!(
+            "{} (retrying in {}ms, {} of {})",
+            err,
+            delay.as_millis(),
+            attempt + 1,
+            self.retries
+        );
+        thread::sleep(delay);
+    }
+}
+
+/// A writer that holds back its output until it is flushed or grows too large, so that it can be
+/// discarded if the query fails and is retried.
+pub struct HeldOutput<W: Write> {
+    inner: W,
+    held: Vec<u8>,
+    limit: usize,
+    released: bool,
+}
+
+impl<W: Write> HeldOutput<W> {
+    /// If retrying isn’t possible, pass false for hold, and the output is written straight through.
+    pub fn new(inner: W, hold: bool) -> Self {
+        HeldOutput {
+            inner,
+            held: Vec::new(),
+            limit: if hold { HELD_OUTPUT_LIMIT } else { 0 },
+            released: false,
+        }
+    }
+
+    /// Discards the held output, returning false if it was too late because some had already been
+    /// written.
+    pub fn discard(&mut self) -> bool {
+        self.held.clear();
+        !self.released
+    }
+
+    fn release(&mut self) -> io::Result<()> {
+        self.released = true;
+        self.inner.write_all(&self.held)?;
+        self.held = Vec::new();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HeldOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.released {
+            return self.inner.write(buf);
+        }
+        self.held.extend_from_slice(buf);
+        if self.held.len() > self.limit {
+            self.release()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.released {
+            self.release()?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas_err::CasErr;
+    use crate::retry::{HeldOutput, RetryPolicy};
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn test_held_output() {
+        let mut out = HeldOutput::new(Vec::new(), true);
+        out.write_all(b"[{\"partial\":").unwrap();
+        assert!(out.discard());
+        out.write_all(b"[]").unwrap();
+        out.flush().unwrap();
+        assert!(!out.discard());
+        assert_eq!(out.inner, b"[]");
+    }
+
+    #[test]
+    fn test_unheld_output() {
+        let mut out = HeldOutput::new(Vec::new(), false);
+        out.write_all(b"[").unwrap();
+        assert!(!out.discard());
+        assert_eq!(out.inner, b"[");
+    }
+
+    #[test]
+    fn test_retries_only_transient_errors() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(0),
+        };
+        let mut attempts = 0;
+        let result: Result<(), CasErr> = policy.run(|| {
+            attempts += 1;
+            Err(CasErr::IoConnLost("connection reset by peer".to_owned()))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: Result<(), CasErr> = policy.run(|| {
+            attempts += 1;
+            Err(CasErr::IoErr("No space left on device".to_owned()))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+        assert!(!CasErr::IoBrokenPipe.is_transient());
+    }
+}
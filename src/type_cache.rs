@@ -0,0 +1,109 @@
+use crate::args::ConnectionParams;
+use crate::cas_err::CasErr;
+use crate::configs;
+use crate::postgres::connection::Conn;
+use crate::postgres::PgType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+
+const CACHE_FILENAME: &str = "types.toml";
+
+/// The types looked up for a saved connection, and the server they were looked up on. They are
+/// only used while the connection still goes to the same server, running the same version.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedTypes {
+    server: String,
+    server_version: String,
+    types: Vec<PgType>,
+}
+
+/// Loads the types saved for the connection into conn, if they were looked up on the same server.
+/// The cache is only an optimisation, so if it can’t be read, the types are looked up again.
+pub fn load(name: &str, conn: &mut Conn) {
+    let mut caches = read_caches().ok().flatten().unwrap_or_default();
+    if let Some(cached) = caches.remove(name) {
+        if cached.server == conn.server()
+            && Some(cached.server_version.as_str()) == conn.server_param("server_version")
+        {
+            conn.load_types(cached.types);
+        }
+    }
+}
+
+/// Saves the types that conn has looked up, if there are any new ones. Failing to save them isn’t
+/// an error, since the query has already succeeded.
+pub fn save_if_looked_up(name: &str, conn: &Conn) {
+    if conn.types_looked_up() {
+        if let Err(err) = save(name, conn) {
+            eprintln!("Could not save the types for connection {}: {}", name, err);
+        }
+    }
+}
+
+/// Looks up the types for the saved connection again, replacing any that were saved.
+pub fn refresh(params: ConnectionParams, name: String) -> Result<(), CasErr> {
+    let mut conn = Conn::connect(params)?;
+    conn.look_up_types()?;
+    save(&name, &conn)?;
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    writeln!(
+        handle,
+        "Types for connection {} refreshed: {} found.",
+        name,
+        conn.dynamic_types().len()
+    )?;
+    Ok(())
+}
+
+/// Removes the types saved for a connection, when the connection is deleted.
+pub fn remove(name: &str) -> Result<(), CasErr> {
+    if let Some(mut caches) = read_caches()? {
+        if caches.remove(name).is_some() {
+            write_caches(&caches)?;
+        }
+    }
+    Ok(())
+}
+
+fn save(name: &str, conn: &Conn) -> Result<(), CasErr> {
+    // A cache that can’t be read is replaced.
+    let mut caches = read_caches().ok().flatten().unwrap_or_default();
+    let mut types = conn.dynamic_types();
+    types.sort_by_key(|pg_type| pg_type.oid);
+    caches.insert(
+        name.to_owned(),
+        CachedTypes {
+            server: conn.server().to_owned(),
+            server_version: conn
+                .server_param("server_version")
+                .unwrap_or_default()
+                .to_owned(),
+            types,
+        },
+    );
+    write_caches(&caches)
+}
+
+fn read_caches() -> Result<Option<HashMap<String, CachedTypes>>, CasErr> {
+    let path = configs::config_dir_path()?.join(CACHE_FILENAME);
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(CasErr::from(err)),
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|e| CasErr::ConfigsErr(format!("Could not parse type cache: {}", e)))
+}
+
+fn write_caches(caches: &HashMap<String, CachedTypes>) -> Result<(), CasErr> {
+    let dir_path = configs::config_dir_path()?;
+    fs::create_dir_all(&dir_path)?;
+    let toml = toml::to_string(caches)
+        .map_err(|e| CasErr::ConfigsErr(format!("Could not serialize type cache: {}", e)))?;
+    fs::write(dir_path.join(CACHE_FILENAME), toml)?;
+    Ok(())
+}
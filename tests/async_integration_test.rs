@@ -0,0 +1,145 @@
+#![cfg(feature = "tokio")]
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::async_connection::Conn;
+use casql::postgres::PgType;
+
+// Requires local test database to be running.
+
+#[tokio::test]
+async fn test_query() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM integers".to_string(), vec![], &mut out)
+        .await?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"bool":true,"int2":12345,"int4":12345678,"int8":123456790123}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_user_defined_types() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM casks".to_string(), vec![], &mut out)
+        .await?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"size":"barrel","volume":164,"sizes":["firkin","hogshead"]}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_oid_invalidates_loaded_types() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let stale = PgType {
+        oid: 1,
+        name: "cask_size".to_string(),
+        typtype: b'e',
+        elem: 0,
+        base_type: 0,
+        relid: 0,
+        namespace: 2200,
+        range_subtype: 0,
+        attributes: vec![],
+    };
+    conn.load_types(vec![stale]);
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM casks".to_string(), vec![], &mut out)
+        .await?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"size":"barrel","volume":164,"sizes":["firkin","hogshead"]}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    assert!(conn.dynamic_types().iter().all(|pg_type| pg_type.oid != 1));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_record_fields_looked_up() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT ROW('barrel'::cask_size, 1) AS record".to_string(),
+        vec![],
+        &mut out,
+    )
+    .await?;
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        "[{\"record\":[\"barrel\",1]}]\n"
+    );
+    // The connection can be used again afterwards.
+    let mut out = Vec::new();
+    conn.query("SELECT 1 AS one".to_string(), vec![], &mut out)
+        .await?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"one\":1}]\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_money() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut out = Vec::new();
+    conn.query("SELECT 12.34::money AS money".to_string(), vec![], &mut out)
+        .await?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"money\":12.34}]\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut rows = conn
+        .stream("SELECT * FROM floats".to_string(), vec![])
+        .await?;
+    let first = rows.next().await.unwrap()?;
+    assert_eq!(
+        std::str::from_utf8(&first).unwrap(),
+        r#"{"float4":3.1415927,"float8":3.141592653589793}"#
+    );
+    // The rest of the rows are skipped when the next query is run.
+    drop(rows);
+    let mut out = Vec::new();
+    conn.query("SELECT 1 AS one".to_string(), vec![], &mut out)
+        .await?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"one\":1}]\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_error() -> Result<(), CasErr> {
+    let mut conn = connect().await?;
+    let mut out = Vec::new();
+    let result = conn
+        .query("SELECT 1/0 AS one".to_string(), vec![], &mut out)
+        .await;
+    assert!(matches!(result, Err(CasErr::ServerErr { code, .. }) if code == "22012"));
+    // The connection can still be used after an error.
+    out.clear();
+    conn.query("SELECT 1 AS one".to_string(), vec![], &mut out)
+        .await?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"one\":1}]\n");
+    Ok(())
+}
+
+async fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params).await
+}
@@ -0,0 +1,77 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use casql::postgres::PgType;
+
+// Requires local test database to be running.
+
+const CASKS: &str = r#"[{"size":"barrel","volume":164,"sizes":["firkin","hogshead"]}]
+"#;
+
+#[test]
+fn test_load_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM casks".to_string(), vec![], &mut out)?;
+    assert!(conn.types_looked_up());
+    let types = conn.dynamic_types();
+    assert!(types.iter().any(|pg_type| pg_type.name == "cask_size"));
+
+    // A later connection doesn’t need to look them up again.
+    let mut conn = connect()?;
+    conn.load_types(types);
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM casks".to_string(), vec![], &mut out)?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), CASKS);
+    assert!(!conn.types_looked_up());
+    Ok(())
+}
+
+#[test]
+fn test_unknown_oid_invalidates_loaded_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let stale = PgType {
+        oid: 1,
+        name: "cask_size".to_string(),
+        typtype: b'e',
+        elem: 0,
+        base_type: 0,
+        relid: 0,
+        namespace: 2200,
+        range_subtype: 0,
+        attributes: vec![],
+    };
+    conn.load_types(vec![stale]);
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM casks".to_string(), vec![], &mut out)?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), CASKS);
+    assert!(conn.types_looked_up());
+    assert!(conn.dynamic_types().iter().all(|pg_type| pg_type.oid != 1));
+    Ok(())
+}
+
+#[test]
+fn test_look_up_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    conn.look_up_types()?;
+    let types = conn.dynamic_types();
+    assert!(types.iter().any(|pg_type| pg_type.name == "cask_volume"));
+    // The row types of tables are left until they are queried.
+    assert!(types.iter().all(|pg_type| pg_type.name != "casks"));
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}
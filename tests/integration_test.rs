@@ -2,6 +2,7 @@ use casql::args;
 use casql::cas_err::CasErr;
 use casql::postgres;
 use casql::postgres::connection::Conn;
+use casql::postgres::{RegTypeFormat, Registry, TextSearchFormat};
 
 // Requires local test database to be running.
 
@@ -201,6 +202,364 @@ fn test_net() -> Result<(), CasErr> {
     Ok(())
 }
 
+/*
+  size  | volume |       sizes
+--------+--------+-------------------
+ barrel |    164 | {firkin,hogshead}
+*/
+#[test]
+fn test_user_defined_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT *, '{cas,cat}'::text[] AS texts FROM casks".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"size":"barrel","volume":164,"sizes":["firkin","hogshead"],"texts":["cas","cat"]}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+ mood  |           moods
+-------+---------------------------
+ happy | {sad,NULL,"so \"happy\""}
+       | {}
+*/
+#[test]
+fn test_enums() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM moods".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"mood":"happy","moods":["sad",null,"so \"happy\""]}"#, r#"{"mood":null,"moods":[]}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_arrays() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM arrays".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}{}{}{}}},{}]\n",
+        r#"{"texts":["cas",null],"uuids":["a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"],"#,
+        r#""timestamptzs":["2021-01-01T12:00:00Z"],"jsonbs":[{"a":1}],"numerics":[1.5,"NaN"],"#,
+        r#""dates":[["2021-01-01","2021-01-02"],["2021-01-03",null]],"#,
+        r#""indexed":{"lower_bounds":[0],"elements":[1,2]}"#,
+        r#"{"texts":[],"uuids":null,"timestamptzs":null,"jsonbs":null,"numerics":null,"dates":null,"indexed":null}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+     email      |  work_email  |     work_emails     | volumes
+----------------+--------------+---------------------+----------
+ root@localhost | root@cas.cat | {root@cas.cat,NULL} | {164,82}
+                |              |                     |
+*/
+#[test]
+fn test_domains() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM emails".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"email":"root@localhost","work_email":"root@cas.cat","work_emails":["root@cas.cat",null],"volumes":[164,82]}"#,
+        r#"{"email":null,"work_email":null,"work_emails":null,"volumes":null}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+     barrel      |      barrels
+-----------------+-------------------
+ (rum,12,firkin) | {"(gin,,barrel)"}
+                 | {}
+*/
+#[test]
+fn test_composites() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM barrels".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"barrel":{"spirit":"rum","years":12,"size":"firkin"},"barrels":[{"spirit":"gin","years":null,"size":"barrel"}]}"#,
+        r#"{"barrel":null,"barrels":[]}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+// Anonymous records have no field names, so they are written as arrays.
+#[test]
+fn test_records() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT ROW(1, 'a') AS record, ROW(NULL, ROW('cas'::text, ARRAY[2])) AS nested".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"record":[1,"a"],"nested":[null,["cas",[2]]]}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_unknown_record_fields() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    // casql can’t decode refcursors, which are sent in binary in a record, so the server converts
+    // them to text after the rows are read.
+    conn.query(
+        "SELECT ROW('cur'::refcursor, 1) AS record, \
+         ROW(ARRAY['a', 'b']::refcursor[]) AS array_record"
+            .to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!("[{}]\n", r#"{"record":["cur",1],"array_record":["{a,b}"]}"#);
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_record_fields_looked_up() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    // The types of a record’s fields are only in its rows, so they are looked up after reading them.
+    conn.query(
+        "SELECT ROW('barrel'::cask_size, 1) AS record, \
+         ROW(ROW(164::cask_volume)) AS nested, \
+         ARRAY[ROW('firkin'::cask_size)] AS records"
+            .to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"record":["barrel",1],"nested":[[164]],"records":[["firkin"]]}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+ int4range | numrange  |          tstzrange          | daterange | floatrange
+-----------+-----------+-----------------------------+-----------+------------
+ [1,10)    | (1.5,2.5] | ["2021-01-01 12:00:00+00",) | empty     | [1.5,2.5]
+           |           |                             |           |
+*/
+#[test]
+fn test_ranges() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM ranges".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}{}{}{}{}}},{}]\n",
+        r#"{"int4range":{"lower":1,"upper":10,"lower_inc":true,"upper_inc":false},"#,
+        r#""numrange":{"lower":1.5,"upper":2.5,"lower_inc":false,"upper_inc":true},"#,
+        r#""tstzrange":{"lower":"2021-01-01T12:00:00Z","upper":null,"lower_inc":true,"upper_inc":false},"#,
+        r#""daterange":"empty","#,
+        r#""floatrange":{"lower":1.5,"upper":2.5,"lower_inc":true,"upper_inc":true}"#,
+        r#"{"int4range":null,"numrange":null,"tstzrange":null,"daterange":null,"floatrange":null}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+            body            |                  query
+----------------------------+-----------------------------------------
+ 'cat':1A,4 'fat':2 'it''s' | 'fat' & !( 'rat' | 'cat':*A ) <2> 'mat'
+                            |
+*/
+#[test]
+fn test_text_search() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM documents".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"body":"'cat':1A,4 'fat':2 'it''s'","query":"'fat' & !( 'rat' | 'cat':*A ) <2> 'mat'"}"#,
+        r#"{"body":"","query":""}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_text_search_structured() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut registry = Registry::new();
+    registry.set_text_search_format(TextSearchFormat::Structured);
+    conn.set_registry(registry)?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM documents".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}{}{}{}{}]\n",
+        r#"{"body":[{"lexeme":"cat","positions":[1,4],"weights":"AD"},{"lexeme":"fat","positions":[2],"weights":"D"},{"lexeme":"it's","positions":[],"weights":""}],"#,
+        r#""query":{"operator":"&","operands":[{"lexeme":"fat","weights":"","prefix":false},"#,
+        r#"{"operator":"<2>","operands":[{"operator":"!","operands":[{"operator":"|","operands":[{"lexeme":"rat","weights":"","prefix":false},"#,
+        r#"{"lexeme":"cat","weights":"A","prefix":true}]}]},{"lexeme":"mat","weights":"","prefix":false}]}]}},"#,
+        r#"{"body":[],"query":null}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+// Money has the number of fraction digits of the server’s lc_monetary, which is 2 for the tests.
+#[test]
+fn test_system_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT 12.34::money AS money, ARRAY[(-0.05)::money] AS moneys, \
+         '16/B374D848'::pg_lsn AS lsn, '42'::xid8 AS xid8, 4294967295::oid AS oid, \
+         '100:104:100,102'::txid_snapshot AS txid_snapshot, '5:5:'::pg_snapshot AS snapshot"
+            .to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}{}{}]\n",
+        r#"{"money":12.34,"moneys":[-0.05],"lsn":"16/B374D848","xid8":"42","oid":4294967295,"#,
+        r#""txid_snapshot":{"xmin":"100","xmax":"104","xip":["100","102"]},"#,
+        r#""snapshot":{"xmin":"5","xmax":"5","xip":[]}}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+const REG_TYPES_QUERY: &str = "SELECT 'pg_class'::regclass AS class, 'now'::regproc AS proc, \
+     'pg_catalog'::regnamespace AS namespace, ARRAY['\"char\"'::regtype, 'int4'::regtype, NULL] AS types, \
+     '[0:0]={int8}'::regtype[] AS bounded";
+
+#[test]
+fn test_reg_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(REG_TYPES_QUERY.to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"class":1259,"proc":1299,"namespace":11,"types":[18,23,null],"bounded":{"lower_bounds":[0],"elements":[20]}}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_reg_type_names() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut registry = Registry::new();
+    registry.set_reg_type_format(RegTypeFormat::Name);
+    conn.set_registry(registry)?;
+    let mut out = Vec::new();
+    conn.query(REG_TYPES_QUERY.to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}{}]\n",
+        r#"{"class":"pg_catalog.pg_class","proc":"pg_catalog.now","namespace":"pg_catalog","#,
+        r#""types":["pg_catalog.\"char\"","pg_catalog.int4",null],"bounded":{"lower_bounds":[0],"elements":["pg_catalog.int8"]}}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+
+    // Within records and composites, and outside the search_path.
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT ROW('pg_class'::regclass, ARRAY['int4'::regtype]) AS record, \
+         ROW('casks', 'cask_size')::cask_ref AS ref, 'warehouse.racks'::regclass AS rack, \
+         0::regclass AS missing"
+            .to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}{}]\n",
+        r#"{"record":["pg_catalog.pg_class",["pg_catalog.int4"]],"#,
+        r#""ref":{"cask":"public.casks","size":"public.cask_size"},"rack":"warehouse.racks","missing":0}"#
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+// hstore is an extension, so its oid is looked up, and its values are written as JSON objects.
+#[test]
+fn test_hstore() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM hstores".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"hstore":{"cas":"cat","say \"hi\"":null},"hstores":[{"a":"1"},{}]}"#,
+        r#"{"hstore":null,"hstores":null}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+// Multiranges need Postgres 14.
+#[test]
+fn test_multiranges() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT '{[1,3), [5,)}'::int4multirange AS ints, '{}'::datemultirange AS dates, \
+         '{[1.5,2]}'::floatmultirange AS floats"
+            .to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{}{}{}]\n",
+        r#"{"ints":[{"lower":1,"upper":3,"lower_inc":true,"upper_inc":false},{"lower":5,"upper":null,"lower_inc":true,"upper_inc":false}],"#,
+        r#""dates":[],"#,
+        r#""floats":[{"lower":1.5,"upper":2.0,"lower_inc":true,"upper_inc":true}]}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+// Types without a binary serialiser are requested as text.
+#[test]
+fn test_unknown_types() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT 'cur'::refcursor AS cursor, 'root=r/root'::aclitem AS acl, 1 AS int".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!("[{}]\n", r#"{"cursor":"cur","acl":"root=r/root","int":1}"#);
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_no_rows() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "CREATE TEMPORARY TABLE casks (id int)".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[]\n");
+    Ok(())
+}
+
 fn connect() -> Result<Conn, CasErr> {
     let params = args::ConnectionParams {
         host: "localhost".to_string(),
@@ -209,6 +568,9 @@ fn connect() -> Result<Conn, CasErr> {
         database: Some("dbname".to_string()),
         port: Some(5432),
         postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
     };
     postgres::connection::Conn::connect(params)
 }
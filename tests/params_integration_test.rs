@@ -0,0 +1,72 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use casql::postgres::params::{Date, Jsonb, Param, Uuid};
+use std::net::IpAddr;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Requires local test database to be running.
+
+#[test]
+fn test_binary_params() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let params = vec![
+        Param::binary(&true)?,
+        Param::binary(&12345i16)?,
+        Param::binary(&123456790123i64)?,
+        Param::binary(&1.5f64)?,
+        Param::binary("cowran")?,
+        Param::binary(&[1u8, 2][..])?,
+        Param::binary(&Uuid::parse("27e31d5b-b544-44e0-83c1-379519b8a115")?)?,
+        Param::binary(&Date::from_ymd(2021, 3, 4).unwrap())?,
+        Param::binary(&(UNIX_EPOCH + Duration::from_secs(1614834367)))?,
+        Param::binary(&Jsonb(r#"{"cas": "cat"}"#))?,
+        Param::binary(&"127.0.0.1".parse::<IpAddr>().unwrap())?,
+        Param::binary(&vec![Some(1i32), None, Some(3)])?,
+        Param::binary(&None::<i32>)?,
+    ];
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT $1 AS bool, $2 AS int2, $3 AS int8, $4 AS float8, $5 AS text, $6 AS bytes, \
+         $7 AS uuid, $8 AS date, $9 AS timestamptz, $10 AS jsonb, $11 AS inet, $12 AS ints, \
+         $13 AS nothing"
+            .to_string(),
+        params,
+        &mut out,
+    )?;
+    let expected = concat!(
+        r#"[{"bool":true,"int2":12345,"int8":123456790123,"float8":1.5,"text":"cowran","#,
+        r#""bytes":[1,2],"uuid":"27e31d5b-b544-44e0-83c1-379519b8a115","date":"2021-03-04","#,
+        r#""timestamptz":"2021-03-04T05:06:07Z","jsonb":{"cas":"cat"},"inet":"127.0.0.1","#,
+        r#""ints":[1,null,3],"nothing":null}]"#,
+        "\n"
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_mixed_formats() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(i64, Option<String>)> = conn.query_as(
+        "SELECT $1::int8 + $2, $3::text".to_string(),
+        vec!["40".into(), Param::binary(&2i64)?, Param::null()],
+    )?;
+    assert_eq!(rows, vec![(42, None)]);
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}
@@ -0,0 +1,172 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::pool::{Pool, PoolOptions};
+use std::thread;
+use std::time::Duration;
+
+// Requires local test database to be running.
+
+#[test]
+fn test_reuse() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions::default())?;
+    let first_pid = backend_pid(&pool)?;
+    let second_pid = backend_pid(&pool)?;
+    assert_eq!(first_pid, second_pid);
+    assert_eq!(pool.size(), 1);
+    assert_eq!(pool.idle(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_reset_session() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions::default())?;
+    {
+        let mut conn = pool.get()?;
+        let mut out = Vec::new();
+        conn.query(
+            "SELECT set_config('application_name', 'pooled', false) AS name".to_string(),
+            vec![],
+            &mut out,
+        )?;
+    }
+    let mut conn = pool.get()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT current_setting('application_name') AS name".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"name\":\"\"}]\n");
+    Ok(())
+}
+
+#[test]
+fn test_server_error_keeps_connection() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions::default())?;
+    {
+        let mut conn = pool.get()?;
+        let mut out = Vec::new();
+        assert!(conn
+            .query("SELECT 1/0 AS one".to_string(), vec![], &mut out)
+            .is_err());
+    }
+    assert_eq!(pool.idle(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_query_as() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions::default())?;
+    let mut conn = pool.get()?;
+    let rows: Vec<(i32, String)> =
+        conn.query_as("SELECT 1 AS id, 'cas' AS name".to_string(), vec![])?;
+    assert_eq!(rows, vec![(1, "cas".to_string())]);
+    let rows = conn.query_rows("SELECT generate_series(1, 10) AS n".to_string(), vec![])?;
+    // The rows that weren’t read are skipped when they are dropped.
+    drop(rows);
+    drop(conn);
+    assert_eq!(pool.idle(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_max_size() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions {
+        max_size: 2,
+        connection_timeout: Duration::from_millis(50),
+        ..PoolOptions::default()
+    })?;
+    let first = pool.get()?;
+    let _second = pool.get()?;
+    assert!(pool.get().is_err());
+    drop(first);
+    assert!(pool.get().is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_threads() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions {
+        min_size: 1,
+        max_size: 2,
+        ..PoolOptions::default()
+    })?;
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let pool = pool.clone();
+            thread::spawn(move || backend_pid(&pool))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+    assert!(pool.size() <= 2);
+    Ok(())
+}
+
+#[test]
+fn test_reset_timeout_is_cleared() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions {
+        connection_timeout: Duration::from_millis(100),
+        ..PoolOptions::default()
+    })?;
+    let first_pid = backend_pid(&pool)?;
+    // The timeout applies to the reset and the health check, not to the queries in between, and
+    // the connection is kept.
+    {
+        let mut conn = pool.get()?;
+        let mut out = Vec::new();
+        conn.query(
+            "SELECT 1 AS slept FROM pg_sleep(0.3)".to_string(),
+            vec![],
+            &mut out,
+        )?;
+    }
+    assert_eq!(backend_pid(&pool)?, first_pid);
+    Ok(())
+}
+
+#[test]
+fn test_idle_timeout() -> Result<(), CasErr> {
+    let pool = pool(PoolOptions {
+        min_size: 1,
+        idle_timeout: Some(Duration::from_millis(10)),
+        ..PoolOptions::default()
+    })?;
+    {
+        let _first = pool.get()?;
+        let _second = pool.get()?;
+    }
+    assert_eq!(pool.size(), 2);
+    thread::sleep(Duration::from_millis(20));
+    // The connection beyond min_size is closed, and the other is kept.
+    pool.get()?;
+    assert_eq!(pool.size(), 1);
+    Ok(())
+}
+
+fn backend_pid(pool: &Pool) -> Result<String, CasErr> {
+    let mut conn = pool.get()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT pg_backend_pid() AS pid".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+fn pool(options: PoolOptions) -> Result<Pool, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Pool::new(params, options)
+}
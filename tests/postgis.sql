@@ -76,4 +76,18 @@ CREATE TABLE geo_coll (
     coll geometry
 );
 
-INSERT INTO geo_coll VALUES ('SRID=32632;GEOMETRYCOLLECTION (POINT (40 10), MULTIPOINT ((40 10),(10 40)),LINESTRING (10 10, 20 20, 10 40),POLYGON ((40 40, 20 45, 45 30, 40 40)))');
\ No newline at end of file
+INSERT INTO geo_coll VALUES ('SRID=32632;GEOMETRYCOLLECTION (POINT (40 10), MULTIPOINT ((40 10),(10 40)),LINESTRING (10 10, 20 20, 10 40),POLYGON ((40 40, 20 45, 45 30, 40 40)))');
+CREATE TABLE geographies (
+    geog geography
+);
+
+INSERT INTO geographies
+VALUES ('POINT(1.5 3.5)'),
+       ('SRID=4326;LINESTRING(1.5 3.5,5.5 7.5)');
+
+CREATE TABLE boxes (
+    box2 box2d,
+    box3 box3d
+);
+
+INSERT INTO boxes VALUES ('BOX(1.5 2.5,3.5 4.5)', 'BOX3D(1.5 2.5 3.5,4.5 5.5 6.5)');
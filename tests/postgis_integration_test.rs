@@ -158,7 +158,7 @@ fn test_multipolys() -> Result<(), CasErr> {
 /*
                                                                                     coll
 ------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
- {"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[40,10]},{"type":"LineString","coordinates":[[10,10],[20,20],[10,40]]},{"type":"Polygon","coordinates":[[[40,40],[20,45],[45,30],[40,40]]]}]}
+ {"type":"GeometryCollection","crs":{"type":"name","properties":{"name":"EPSG:32632"}},"geometries":[{"type":"Point","coordinates":[40,10]},{"type":"MultiPoint","coordinates":[[40,10],[10,40]]},{"type":"LineString","coordinates":[[10,10],[20,20],[10,40]]},{"type":"Polygon","coordinates":[[[40,40],[20,45],[45,30],[40,40]]]}]}
 */
 #[test]
 fn test_geometry_collections() -> Result<(), CasErr> {
@@ -167,7 +167,63 @@ fn test_geometry_collections() -> Result<(), CasErr> {
     conn.query("SELECT * FROM geo_coll".to_string(), vec![], &mut out)?;
     let expected = format!(
         "[{}]\n",
-        r#"{"coll":{"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[40,10]},{"type":"LineString","coordinates":[[10,10],[20,20],[10,40]]},{"type":"Polygon","coordinates":[[[40,40],[20,45],[45,30],[40,40]]]}]}}"#,
+        r#"{"coll":{"type":"GeometryCollection","crs":{"type":"name","properties":{"name":"EPSG:32632"}},"geometries":[{"type":"Point","coordinates":[40,10]},{"type":"MultiPoint","coordinates":[[40,10],[10,40]]},{"type":"LineString","coordinates":[[10,10],[20,20],[10,40]]},{"type":"Polygon","coordinates":[[[40,40],[20,45],[45,30],[40,40]]]}]}}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+                                                            geog
+---------------------------------------------------------------------------------------------------------------------------
+ {"type":"Point","crs":{"type":"name","properties":{"name":"EPSG:4326"}},"coordinates":[1.5,3.5]}
+ {"type":"LineString","crs":{"type":"name","properties":{"name":"EPSG:4326"}},"coordinates":[[1.5,3.5],[5.5,7.5]]}
+*/
+#[test]
+fn test_geographies() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM geographies".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"geog":{"type":"Point","crs":{"type":"name","properties":{"name":"EPSG:4326"}},"coordinates":[1.5,3.5]}}"#,
+        r#"{"geog":{"type":"LineString","crs":{"type":"name","properties":{"name":"EPSG:4326"}},"coordinates":[[1.5,3.5],[5.5,7.5]]}}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+/*
+         box2          |              box3
+-----------------------+--------------------------------
+ BOX(1.5 2.5,3.5 4.5)  | BOX3D(1.5 2.5 3.5,4.5 5.5 6.5)
+*/
+#[test]
+fn test_boxes() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query("SELECT * FROM boxes".to_string(), vec![], &mut out)?;
+    let expected = format!(
+        "[{}]\n",
+        r#"{"box2":[1.5,2.5,3.5,4.5],"box3":[1.5,2.5,3.5,4.5,5.5,6.5]}"#,
+    );
+    assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_geometry_dumps() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT ST_Dump('MULTIPOINT((1.5 2.5),(3.5 4.5))'::geometry) AS dump".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    let expected = format!(
+        "[{},{}]\n",
+        r#"{"dump":{"path":[1],"geom":{"type":"Point","coordinates":[1.5,2.5]}}}"#,
+        r#"{"dump":{"path":[2],"geom":{"type":"Point","coordinates":[3.5,4.5]}}}"#,
     );
     assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
     Ok(())
@@ -181,6 +237,9 @@ fn connect() -> Result<Conn, CasErr> {
         database: Some("dbname".to_string()),
         port: Some(5432),
         postgis: true,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
     };
     postgres::connection::Conn::connect(params)
 }
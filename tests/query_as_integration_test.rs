@@ -0,0 +1,208 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use serde::Deserialize;
+
+// Requires local test database to be running.
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Integers {
+    bool: bool,
+    int2: i16,
+    int4: i64,
+    int8: i64,
+}
+
+#[test]
+fn test_struct() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<Integers> = conn.query_as("SELECT * FROM integers".to_string(), vec![])?;
+    assert_eq!(
+        rows,
+        vec![Integers {
+            bool: true,
+            int2: 12345,
+            int4: 12345678,
+            int8: 123456790123,
+        }]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tuple_and_option() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(Option<String>, Option<f64>, Option<i64>)> = conn.query_as(
+        "SELECT numeric::text, fixed_scale, zero_scale FROM numerics WHERE numeric IS NULL OR numeric = 1"
+            .to_string(),
+        vec![],
+    )?;
+    assert_eq!(
+        rows,
+        vec![
+            (Some("1".to_string()), Some(3.0), Some(2)),
+            (None, None, None),
+        ]
+    );
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Structured {
+    uuid: String,
+    jsonb: String,
+    date: String,
+    timestamp: String,
+    bytes: Vec<u8>,
+    ints: Vec<Vec<i32>>,
+}
+
+#[test]
+fn test_formatted_values() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<Structured> = conn.query_as(
+        "SELECT uuid, jsonb, '2021-03-04'::date AS date, '2021-03-04 05:06:07'::timestamp AS timestamp, \
+         '\\x0102'::bytea AS bytes, '{{1,2},{3,4}}'::int4[] AS ints FROM structured_data"
+            .to_string(),
+        vec![],
+    )?;
+    assert_eq!(
+        rows,
+        vec![Structured {
+            uuid: "27e31d5b-b544-44e0-83c1-379519b8a115".to_string(),
+            jsonb: r#"{"cas":"cat"}"#.to_string(),
+            date: "2021-03-04".to_string(),
+            timestamp: "2021-03-04T05:06:07Z".to_string(),
+            bytes: vec![1, 2],
+            ints: vec![vec![1, 2], vec![3, 4]],
+        }]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_single_column() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<String> = conn.query_as("SELECT text FROM texts".to_string(), vec![])?;
+    assert_eq!(rows, vec!["cowran".to_string()]);
+    Ok(())
+}
+
+// xid8s are written as strings, since they are unsigned 64-bit, but can still be read as numbers.
+#[test]
+fn test_xid8() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(u64, String)> = conn.query_as(
+        "SELECT '18446744073709551615'::xid8, '42'::xid8".to_string(),
+        vec![],
+    )?;
+    assert_eq!(rows, vec![(u64::MAX, "42".to_string())]);
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Mood {
+    Sad,
+    Ok,
+    Happy,
+}
+
+#[test]
+fn test_enum() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(Option<Mood>, Vec<Option<Mood>>)> = conn.query_as(
+        "SELECT mood, moods[1:2] FROM moods ORDER BY mood".to_string(),
+        vec![],
+    )?;
+    assert_eq!(
+        rows,
+        vec![
+            (Some(Mood::Happy), vec![Some(Mood::Sad), None]),
+            (None, vec![])
+        ]
+    );
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Barrel {
+    spirit: String,
+    years: Option<i32>,
+    size: String,
+}
+
+#[test]
+fn test_composite() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(Barrel,)> = conn.query_as(
+        "SELECT barrel FROM barrels WHERE barrel IS NOT NULL".to_string(),
+        vec![],
+    )?;
+    assert_eq!(
+        rows,
+        vec![(Barrel {
+            spirit: "rum".to_string(),
+            years: Some(12),
+            size: "firkin".to_string(),
+        },)]
+    );
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Bounds {
+    lower: Option<i32>,
+    upper: Option<i32>,
+    lower_inc: bool,
+    upper_inc: bool,
+}
+
+// Ranges and records are deserialized as maps and sequences, rather than as JSON text.
+#[test]
+fn test_ranges_and_records() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows: Vec<(Bounds, (String, (i32, Vec<bool>)))> = conn.query_as(
+        "SELECT int4range(1, 10), ROW('cask', ROW(2, ARRAY[true, false]))".to_string(),
+        vec![],
+    )?;
+    let bounds = Bounds {
+        lower: Some(1),
+        upper: Some(10),
+        lower_inc: true,
+        upper_inc: false,
+    };
+    let record = ("cask".to_string(), (2, vec![true, false]));
+    assert_eq!(rows, vec![(bounds, record)]);
+
+    let result: Result<Vec<String>, CasErr> = conn.query_as("SELECT ROW(1, 2)".to_string(), vec![]);
+    assert!(matches!(result, Err(CasErr::DecodeErr(_))));
+    Ok(())
+}
+
+#[test]
+fn test_decode_error() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let result: Result<Vec<i16>, CasErr> =
+        conn.query_as("SELECT int8 FROM integers".to_string(), vec![]);
+    assert!(matches!(result, Err(CasErr::DecodeErr(_))));
+    // The rest of the response was read, so the connection can be used again.
+    let rows: Vec<i32> = conn.query_as("SELECT 1".to_string(), vec![])?;
+    assert_eq!(rows, vec![1]);
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}
@@ -0,0 +1,72 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use casql::postgres::Value;
+
+// Requires local test database to be running.
+
+#[test]
+fn test_values() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let rows = conn.query_rows(
+        "SELECT int4, '1.50'::numeric AS num, 'cas'::text AS text, '2021-03-04'::date AS date, \
+         point(1, 2) AS point, '{{1,NULL}}'::int4[] AS ints, '{}'::int4[] AS empty, NULL AS nothing \
+         FROM integers"
+            .to_string(),
+        vec![],
+    )?;
+    let rows = rows.collect::<Result<Vec<_>, CasErr>>()?;
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!(
+        row.columns(),
+        ["int4", "num", "text", "date", "point", "ints", "empty", "nothing"]
+    );
+    assert_eq!(
+        row.values(),
+        [
+            Value::Int(12345678),
+            Value::Numeric("1.50".to_string()),
+            Value::Text("cas".to_string()),
+            Value::Date("2021-03-04".to_string()),
+            Value::Point(1.0, 2.0),
+            Value::Array(vec![Value::Array(vec![Value::Int(1), Value::Null])]),
+            Value::Array(vec![]),
+            Value::Null,
+        ]
+    );
+    assert_eq!(row.get("text"), Some(&Value::Text("cas".to_string())));
+    assert_eq!(row.get("missing"), None);
+    Ok(())
+}
+
+#[test]
+fn test_dropped_rows() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut rows = conn.query_rows("SELECT generate_series(1, 100) AS n".to_string(), vec![])?;
+    assert_eq!(rows.next().unwrap()?.into_values(), vec![Value::Int(1)]);
+    // The rest of the rows are read when they are dropped.
+    drop(rows);
+    let mut rows = conn.query_rows("SELECT 'next'::text AS t".to_string(), vec![])?;
+    assert_eq!(
+        rows.next().unwrap()?.into_values(),
+        vec![Value::Text("next".to_string())]
+    );
+    assert!(rows.next().is_none());
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}
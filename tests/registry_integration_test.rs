@@ -0,0 +1,69 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use casql::postgres::{Registry, Value};
+
+// Requires local test database to be running.
+
+#[test]
+fn test_override_by_oid() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut registry = Registry::new();
+    let numeric = registry.find(1700);
+    registry.register_oid(1700, move |bytes: &[u8], registry: &Registry| match numeric
+        .decode(bytes, registry)?
+    {
+        Value::Numeric(num) => Ok(Value::Text(num)),
+        value => Ok(value),
+    });
+    conn.set_registry(registry)?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT 1.50::numeric AS num, 'NaN'::numeric AS nan".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        "[{\"num\":\"1.50\",\"nan\":\"NaN\"}]\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_register_by_name() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut registry = Registry::new();
+    registry.register_name("int2", |bytes: &[u8], _: &Registry| {
+        Ok(Value::Int(
+            i16::from_be_bytes([bytes[0], bytes[1]]) as i64 * 2,
+        ))
+    });
+    conn.set_registry(registry)?;
+    let mut out = Vec::new();
+    conn.query(
+        "SELECT int2, int4 FROM integers".to_string(),
+        vec![],
+        &mut out,
+    )?;
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        "[{\"int2\":24690,\"int4\":12345678}]\n"
+    );
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}
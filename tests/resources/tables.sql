@@ -132,6 +132,127 @@ INSERT INTO networking
 VALUES ('192.168.100.128/25', '08-00-2b-01-02-03-04-05', '08-00-2b-01-02-03', '127.0.0.1'),
        ('2001:db8::8a2e:370:7334/128', NULL, NULL, '2001:db8::8a2e:370:7334/120');
 
+-- Arrays --
+CREATE TABLE arrays
+(
+    texts        text[],
+    uuids        uuid[],
+    timestamptzs timestamptz[],
+    jsonbs       jsonb[],
+    numerics     numeric[],
+    dates        date[][],
+    indexed      int4[] -- with a lower bound other than 1
+);
+
+INSERT INTO arrays
+VALUES ('{cas,NULL}', '{a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11}', '{"2021-01-01 12:00:00+00"}',
+        ARRAY ['{"a": 1}'::jsonb], '{1.5,NaN}', '{{2021-01-01,2021-01-02},{2021-01-03,NULL}}', '[0:1]={1,2}'),
+       ('{}', NULL, NULL, NULL, NULL, NULL, NULL);
+
+-- User-Defined Types --
+CREATE TYPE cask_size AS ENUM ('firkin', 'barrel', 'hogshead');
+CREATE DOMAIN cask_volume AS int4 CHECK (VALUE > 0);
+
+CREATE TABLE casks
+(
+    size   cask_size,   -- enum, looked up in pg_type
+    volume cask_volume, -- domain over int4
+    sizes  cask_size[]  -- array of the enum
+);
+
+INSERT INTO casks
+VALUES ('barrel', 164, '{firkin,hogshead}');
+
+CREATE TYPE mood AS ENUM ('sad', 'ok', 'happy', 'so "happy"');
+
+CREATE TABLE moods
+(
+    mood  mood,  -- enum, sent as its label
+    moods mood[] -- array of the enum
+);
+
+INSERT INTO moods
+VALUES ('happy', '{sad,NULL,"so \"happy\""}'),
+       (NULL, '{}');
+
+CREATE TYPE barrel AS (spirit text, years int4, size cask_size);
+
+CREATE TABLE barrels
+(
+    barrel  barrel,  -- composite, decoded with its attribute names
+    barrels barrel[] -- array of the composite
+);
+
+INSERT INTO barrels
+VALUES (ROW ('rum', 12, 'firkin'), ARRAY [ROW ('gin', NULL, 'barrel')::barrel]),
+       (NULL, '{}');
+
+CREATE DOMAIN email AS text CHECK (VALUE LIKE '%@%');
+CREATE DOMAIN work_email AS email CHECK (VALUE LIKE '%@cas.cat');
+CREATE DOMAIN cask_volumes AS cask_volume[];
+
+CREATE TABLE emails
+(
+    email       email,        -- domain over text
+    work_email  work_email,   -- domain over a domain
+    work_emails work_email[], -- array of the stacked domain
+    volumes     cask_volumes  -- domain over an array of a domain
+);
+
+INSERT INTO emails
+VALUES ('root@localhost', 'root@cas.cat', '{root@cas.cat,NULL}', '{164,82}'),
+       (NULL, NULL, NULL, NULL);
+
+-- Full-Text Search --
+CREATE TABLE documents
+(
+    body  tsvector,
+    query tsquery
+);
+
+INSERT INTO documents
+VALUES ('cat:1A,4 fat:2 it''s', 'fat & !(rat | cat:*A) <2> mat'),
+       ('', '');
+
+-- Extensions --
+CREATE EXTENSION IF NOT EXISTS hstore;
+
+CREATE TABLE hstores
+(
+    hstore  hstore,  -- looked up by name
+    hstores hstore[]
+);
+
+INSERT INTO hstores
+VALUES ('cas => cat, "say \"hi\"" => NULL', ARRAY ['a => 1'::hstore, '']),
+       (NULL, NULL);
+
+-- Ranges --
+CREATE TYPE floatrange AS RANGE (subtype = float8);
+
+CREATE TABLE ranges
+(
+    int4range  int4range,
+    numrange   numrange,
+    tstzrange  tstzrange,
+    daterange  daterange,
+    floatrange floatrange -- user-defined, looked up in pg_range
+);
+
+INSERT INTO ranges
+VALUES ('[1,10)', '(1.5,2.5]', '[2021-01-01 12:00:00+00,)', 'empty', '[1.5,2.5]'),
+       (NULL, NULL, NULL, NULL, NULL);
+
+-- Reg Types --
+CREATE SCHEMA warehouse; -- not in the search_path
+
+CREATE TABLE warehouse.racks
+(
+    rack int4
+);
+
+CREATE TYPE cask_ref AS (cask regclass, size regtype); -- composite of reg types
+
 /*
 -- Internal Ids --
 regproc regproc, -- 24: registered procedure
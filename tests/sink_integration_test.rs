@@ -0,0 +1,147 @@
+use casql::args;
+use casql::cas_err::CasErr;
+use casql::postgres::connection::Conn;
+use casql::postgres::{JsonSink, Sink, Value};
+
+// Requires local test database to be running.
+
+/// Writes each row as a line of tab-separated values, after a header line of the column names.
+#[derive(Default)]
+struct TsvSink {
+    lines: Vec<String>,
+    line: Vec<String>,
+}
+
+impl Sink for TsvSink {
+    fn begin_result(&mut self, columns: &[String]) -> Result<(), CasErr> {
+        self.lines.push(columns.join("\t"));
+        Ok(())
+    }
+
+    fn begin_row(&mut self) -> Result<(), CasErr> {
+        self.line.clear();
+        Ok(())
+    }
+
+    fn field_name(&mut self, _name: &str) -> Result<(), CasErr> {
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), CasErr> {
+        let text = match value {
+            Value::Null => String::new(),
+            Value::Int(int) => int.to_string(),
+            Value::Text(text) => text.clone(),
+            value => format!("{:?}", value),
+        };
+        self.line.push(text);
+        Ok(())
+    }
+
+    fn end_row(&mut self) -> Result<(), CasErr> {
+        self.lines.push(self.line.join("\t"));
+        Ok(())
+    }
+
+    fn end_result(&mut self) -> Result<(), CasErr> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_sink() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut sink = TsvSink::default();
+    conn.query_to(
+        "SELECT * FROM (VALUES (1, 'cas'), (2, NULL)) AS t (id, name)".to_string(),
+        vec![],
+        &mut sink,
+    )?;
+    assert_eq!(sink.lines, vec!["id\tname", "1\tcas", "2\t"]);
+    Ok(())
+}
+
+#[test]
+fn test_json_sink() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut sink = JsonSink::new(Vec::new());
+    conn.query_to(
+        "SELECT * FROM (VALUES (1, 'cas'), (2, NULL)) AS t (id, name)".to_string(),
+        vec![],
+        &mut sink,
+    )?;
+    assert_eq!(
+        std::str::from_utf8(&sink.into_inner()).unwrap(),
+        "[{\"id\":1,\"name\":\"cas\"},{\"id\":2,\"name\":null}]\n"
+    );
+    Ok(())
+}
+
+/// Fails after the first row.
+#[derive(Default)]
+struct FailingSink {
+    rows: usize,
+}
+
+impl Sink for FailingSink {
+    fn begin_result(&mut self, _columns: &[String]) -> Result<(), CasErr> {
+        Ok(())
+    }
+
+    fn begin_row(&mut self) -> Result<(), CasErr> {
+        Ok(())
+    }
+
+    fn field_name(&mut self, _name: &str) -> Result<(), CasErr> {
+        Ok(())
+    }
+
+    fn value(&mut self, _value: &Value) -> Result<(), CasErr> {
+        Ok(())
+    }
+
+    fn end_row(&mut self) -> Result<(), CasErr> {
+        self.rows += 1;
+        if self.rows > 1 {
+            return Err(CasErr::IoErr("sink is full".to_string()));
+        }
+        Ok(())
+    }
+
+    fn end_result(&mut self) -> Result<(), CasErr> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sink_error_skips_rest_of_rows() -> Result<(), CasErr> {
+    let mut conn = connect()?;
+    let mut sink = FailingSink::default();
+    assert!(conn
+        .query_to(
+            "SELECT generate_series(1, 1000) AS n".to_string(),
+            vec![],
+            &mut sink,
+        )
+        .is_err());
+    // The rest of the rows were skipped, so the connection can be used again.
+    let mut out = Vec::new();
+    conn.query("SELECT 1 AS one".to_string(), vec![], &mut out)?;
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "[{\"one\":1}]\n");
+    Ok(())
+}
+
+fn connect() -> Result<Conn, CasErr> {
+    let params = args::ConnectionParams {
+        host: "localhost".to_string(),
+        user: "root".to_string(),
+        password: Some("cascat".to_string()),
+        database: Some("dbname".to_string()),
+        port: Some(5432),
+        postgis: false,
+        target_session_attrs: args::TargetSessionAttrs::Any,
+        load_balance_hosts: false,
+        read_only: false,
+    };
+    Conn::connect(params)
+}